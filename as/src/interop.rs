@@ -1,15 +1,58 @@
 use crate::parser::{Builtin, Node};
-use shared::{asm::Instruction, err::T8Err};
+use shared::{
+    asm::Instruction,
+    debug::{DebugInfo, Loc, Symbol},
+    err::T8Err,
+    scriptorium::Word,
+    superopt::{self, Constraints},
+};
 use std::collections::HashMap;
 
 pub struct Ctx<'ctx> {
     constants: HashMap<&'ctx str, u8>,
+    /// amount of instructions lowered so far, equal to the pc of the next instruction
+    pc: usize,
+    pub debug: DebugInfo,
 }
 
 impl<'ctx> Ctx<'ctx> {
-    pub fn new() -> Self {
+    pub fn new<S: Into<String>>(file: S) -> Self {
         Ctx {
             constants: HashMap::new(),
+            pc: 0,
+            debug: DebugInfo::new(file),
+        }
+    }
+
+    /// span of an operand spelled as a constant, starting at its `#` or `[` prefix
+    fn operand_span(node: &Node<'ctx>) -> Option<(usize, usize, usize)> {
+        match node {
+            Node::Literal(inner) => match **inner {
+                Node::Ident { pos, inner } => Some((pos.0, pos.1, inner.len() + 1)),
+                _ => None,
+            },
+            Node::Addr(inner) => match **inner {
+                Node::Ident { pos, inner } => Some((pos.0, pos.1, inner.len() + 2)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// label pointing at the `.const <name>` part of the definition of `name`
+    fn defined_here(&self, err: T8Err, name: &str, msg: &str) -> T8Err {
+        match self.debug.symbol(name) {
+            Some(sym) => err.with_label(sym.line, sym.col, ".const ".len() + name.len(), msg),
+            None => err,
+        }
+    }
+
+    /// name of the constant used as an operand, if any
+    fn operand_name(node: &Node<'ctx>) -> Option<String> {
+        match node {
+            Node::Literal(node) | Node::Addr(node) => Self::operand_name(node),
+            Node::Ident { inner, .. } => Some(inner.to_string()),
+            _ => None,
         }
     }
 
@@ -17,56 +60,222 @@ impl<'ctx> Ctx<'ctx> {
         match node {
             Node::Literal(node) | Node::Addr(node) => self.walk_asm_node(*node),
             Node::Number(n) => Ok(n),
-            Node::Ident { pos, inner } => Ok(*self.constants.get(inner).ok_or_else(|| T8Err {
-                line: pos.0,
-                col: pos.1,
-                msg: format!("Undefined identifier `{:?}`", inner),
-            })?),
+            // operands are prefixed by `#` or `[`
+            Node::Ident { pos, inner } => self.constant(pos.0, pos.1 + 1, inner),
             _ => unreachable!(),
         }
     }
 
-    /// used in the assembler for lowering assembly ast to t8 machine code
-    pub fn node_to_instruction(&mut self, node: Node<'ctx>) -> Result<Option<Instruction>, T8Err> {
+    fn constant(&self, line: usize, col: usize, name: &str) -> Result<u8, T8Err> {
+        self.constants.get(name).copied().ok_or_else(|| {
+            T8Err::new(line, col, format!("Undefined identifier `{name}`"))
+                .with_code("E0301")
+                .with_len(name.len())
+                .with_help(format!(
+                    "define it before its first use via `.const {name} <value>`"
+                ))
+        })
+    }
+
+    fn push_loc(&mut self, pos: (usize, usize), operand: Option<String>) {
+        self.debug.locs.push(Loc {
+            pc: self.pc,
+            line: pos.0,
+            col: pos.1,
+            operand,
+        });
+        self.pc += 1;
+    }
+
+    /// used in the assembler for lowering assembly ast to t8 machine code, a node lowers to no
+    /// words for `.const`, a sequence for `.load` and a single word otherwise
+    pub fn node_to_words(&mut self, node: Node<'ctx>) -> Result<Vec<Word>, T8Err> {
         match node {
-            Node::Builtin { kind, lhs, rhs } => {
+            Node::Builtin {
+                pos,
+                kind,
+                lhs,
+                rhs,
+            } => {
                 match kind {
                     Builtin::Const => {
                         let Node::Number(n) = *rhs else {
                             unreachable!();
                         };
                         self.constants.insert(lhs, n);
+                        self.debug.symbols.push(Symbol {
+                            name: lhs.to_string(),
+                            value: n,
+                            line: pos.0,
+                            col: pos.1,
+                        });
                     }
+                    Builtin::Byte | Builtin::Load => unreachable!(),
                 }
-                Ok(None)
+                Ok(vec![])
             }
-            Node::Instruction { partial, rhs } => {
-                let i = if let Some(rhs) = rhs {
-                    match partial {
-                        Instruction::LOADI { .. } => Some(Instruction::LOADI {
-                            imm: self.walk_asm_node(*rhs)?,
-                        }),
-                        Instruction::ST { .. } => Some(Instruction::ST {
-                            addr: self.walk_asm_node(*rhs)?,
-                        }),
-                        Instruction::LD { .. } => Some(Instruction::LD {
-                            addr: self.walk_asm_node(*rhs)?,
-                        }),
-                        Instruction::ROL { .. } => Some(Instruction::ROL {
-                            imm: self.walk_asm_node(*rhs)?,
-                        }),
-                        _ => None,
+            Node::Byte { pos, value } => {
+                self.push_loc(pos, None);
+                Ok(vec![Word::Byte(value)])
+            }
+            Node::Load { pos, value, keep } => {
+                let (value, name) = match *value {
+                    Node::Number(n) => (n, None),
+                    Node::Ident { pos, inner } => {
+                        (self.constant(pos.0, pos.1, inner)?, Some(inner))
                     }
-                } else {
-                    None
+                    _ => unreachable!(),
                 };
+                // DEST is unknown at this point, so a sequence keeping it intact consists of LOADI
+                // and ROL only and is never longer than one overwriting it
+                let preserve = superopt::materialize(
+                    value,
+                    Constraints {
+                        dest: None,
+                        preserve_dest: true,
+                    },
+                );
+                let seq = match preserve {
+                    Some(preserve) => preserve,
+                    None if keep => {
+                        let err = T8Err::new(
+                            pos.0,
+                            pos.1,
+                            format!("0x{value:02X} can not be loaded without overwriting DEST"),
+                        )
+                        .with_len(".load".len())
+                        .with_code("E0303")
+                        .with_help(
+                            "only rotations of 4 bit values keep DEST, drop `keep` and save DEST \
+                             in memory around the `.load`",
+                        );
+                        return Err(match name {
+                            Some(name) => self.defined_here(err, name, "constant defined here"),
+                            None => err,
+                        });
+                    }
+                    None => superopt::materialize(value, Constraints::default())
+                        .expect("every value is reachable if DEST may be overwritten"),
+                };
+                Ok(seq
+                    .into_iter()
+                    .map(|i| {
+                        self.push_loc(pos, None);
+                        Word::Instruction(i)
+                    })
+                    .collect())
+            }
+            Node::Instruction { pos, partial, rhs } => {
+                let name = rhs.as_deref().and_then(Self::operand_name);
+                self.push_loc(pos, name.clone());
+
+                let Some(rhs) = rhs else {
+                    return Ok(vec![Word::Instruction(partial)]);
+                };
+                let span = Self::operand_span(&rhs);
+                let value = self.walk_asm_node(*rhs)?;
+                if value > 0xF {
+                    let mnemonic = partial.to_str_lossy();
+                    let (line, col, len) = span.unwrap_or((pos.0, pos.1, mnemonic.len()));
+                    let err = T8Err::new(
+                        line,
+                        col,
+                        format!("Operand {value} of {mnemonic} does not fit into 4 bits"),
+                    )
+                    .with_code("E0302")
+                    .with_len(len)
+                    .with_help("immediates and addresses range from 0 to 15, build larger values via LOADI, ROL and ADD");
+                    return Err(match name {
+                        Some(name) => self.defined_here(err, &name, "constant defined here"),
+                        None => err,
+                    });
+                }
 
-                Ok(match i {
-                    None => Some(partial.clone()),
-                    _ => i,
-                })
+                Ok(vec![Word::Instruction(match partial {
+                    Instruction::LOADI { .. } => Instruction::LOADI { imm: value },
+                    Instruction::ST { .. } => Instruction::ST { addr: value },
+                    Instruction::LD { .. } => Instruction::LD { addr: value },
+                    Instruction::ROL { .. } => Instruction::ROL { imm: value },
+                    _ => partial,
+                })])
             }
             _ => unreachable!("{:?}", node),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::assemble;
+    use shared::{asm::Instruction, scriptorium::Word};
+
+    #[test]
+    fn test_operand_too_large_points_at_constant() {
+        let err = assemble(b".const big 20\nLOADI #big", "big.t8").unwrap_err();
+        assert_eq!((err.line, err.col, err.len), (1, 6, 4));
+        assert_eq!(err.labels.len(), 1);
+        assert_eq!((err.labels[0].line, err.labels[0].col), (0, 0));
+    }
+
+    #[test]
+    fn test_load_expands_to_shortest_sequence() {
+        let assembled = assemble(b".const h 0x48\n.load h\nMOV\n.load 0x55", "l.t8").unwrap();
+        assert_eq!(
+            assembled.words,
+            vec![
+                Word::Instruction(Instruction::LOADI { imm: 9 }),
+                Word::Instruction(Instruction::ROL { imm: 3 }),
+                Word::Instruction(Instruction::MOV),
+                Word::Instruction(Instruction::LOADI { imm: 5 }),
+                Word::Instruction(Instruction::MOV),
+                Word::Instruction(Instruction::ROL { imm: 4 }),
+                Word::Instruction(Instruction::ADD),
+            ]
+        );
+        let lines = assembled
+            .debug
+            .locs
+            .iter()
+            .map(|l| l.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 1, 2, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_load_keep_preserves_dest() {
+        let mov = Word::Instruction(Instruction::MOV);
+        assert!(
+            assemble(b".load 0x55", "k.t8")
+                .unwrap()
+                .words
+                .contains(&mov)
+        );
+        assert_eq!(
+            assemble(b".load 0x48 keep", "k.t8").unwrap().words,
+            vec![
+                Word::Instruction(Instruction::LOADI { imm: 9 }),
+                Word::Instruction(Instruction::ROL { imm: 3 }),
+            ]
+        );
+
+        let err = assemble(b".const odd 0x55\n.load odd keep", "k.t8").unwrap_err();
+        assert_eq!((err.line, err.code), (1, Some("E0303")));
+        assert_eq!(err.labels.len(), 1);
+    }
+
+    #[test]
+    fn test_mixed_case_and_tab_indented_source() {
+        let assembled = assemble(
+            b".const led_2 0xF\r\n\tloadi #1\r\n\tSt [led_2]\r\n",
+            "c.t8",
+        )
+        .unwrap();
+        assert_eq!(
+            assembled.words,
+            vec![
+                Word::Instruction(Instruction::LOADI { imm: 1 }),
+                Word::Instruction(Instruction::ST { addr: 0xF }),
+            ]
+        );
+    }
+}
@@ -7,12 +7,16 @@ pub struct Lexer<'lex> {
     pos: usize,
     line: usize,
     col: usize,
+    /// emit [`TokenInner::Comment`] instead of skipping comments
+    comments: bool,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct Token<'tok> {
     pub line: usize,
     pub col: usize,
+    /// amount of characters the token spans
+    pub len: usize,
     pub inner: TokenInner<'tok>,
 }
 
@@ -24,6 +28,8 @@ pub enum TokenInner<'tok> {
     LeftBraket,
     RightBraket,
     Number(u8),
+    /// `; ...` up to the end of the line, only produced via [`Lexer::with_comments`]
+    Comment(&'tok [u8]),
 }
 
 impl<'tok> fmt::Debug for TokenInner<'tok> {
@@ -35,6 +41,7 @@ impl<'tok> fmt::Debug for TokenInner<'tok> {
             TokenInner::LeftBraket => write!(f, "LeftBracket"),
             TokenInner::RightBraket => write!(f, "RightBracket"),
             TokenInner::Number(n) => write!(f, "Number({})", n),
+            TokenInner::Comment(text) => write!(f, "Comment({})", String::from_utf8_lossy(text)),
         }
     }
 }
@@ -46,25 +53,46 @@ impl<'lex> Lexer<'lex> {
             line: 0,
             pos: 0,
             col: 0,
+            comments: false,
         }
     }
 
+    /// keep comments as tokens, the parser does not accept them, this is for tooling like the
+    /// formatter
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
     fn tok(&self, inner: TokenInner<'lex>) -> Token<'lex> {
         Token {
             line: self.line,
             col: self.col,
+            len: 1,
             inner,
         }
     }
 
-    fn err<S: Into<String>>(&self, msg: S) -> T8Err {
-        T8Err {
+    /// token starting at `col` and ending at the current position, used for tokens spanning
+    /// multiple characters
+    fn tok_at(&self, col: usize, inner: TokenInner<'lex>) -> Token<'lex> {
+        Token {
             line: self.line,
-            col: self.col,
-            msg: msg.into(),
+            col,
+            len: self.col - col,
+            inner,
         }
     }
 
+    fn err<S: Into<String>>(&self, msg: S) -> T8Err {
+        T8Err::new(self.line, self.col, msg)
+    }
+
+    /// error spanning from `col` to the current position
+    fn err_at<S: Into<String>>(&self, col: usize, msg: S) -> T8Err {
+        T8Err::new(self.line, col, msg).with_len(self.col - col)
+    }
+
     fn end(&self) -> bool {
         self.cur().is_none()
     }
@@ -87,26 +115,44 @@ impl<'lex> Lexer<'lex> {
 
             match *c as char {
                 ';' => {
+                    let col = self.col;
+                    let start = self.pos;
                     while self.cur().is_some_and(|b| *b != b'\n') {
                         self.advance();
                     }
+                    if self.comments {
+                        let text = self.src[start..self.pos].trim_ascii_end();
+                        toks.push(Token {
+                            line: self.line,
+                            col,
+                            len: text.len(),
+                            inner: TokenInner::Comment(text),
+                        })
+                    }
                 }
-                '\n' | '\r' => {
+                '\n' => {
+                    // advance first, it moves the column, which has to start at 0 on the next line
+                    self.advance();
                     self.line += 1;
                     self.col = 0;
-                    self.advance();
                 }
-                ' ' => self.advance(),
+                ' ' | '\t' | '\r' => self.advance(),
                 '.' => {
+                    let col = self.col;
                     self.advance();
                     if !self.cur().is_some_and(|b| b.is_ascii_alphabetic()) {
-                        return Err(self.err("A '.' requires a following builtin name"));
+                        return Err(self
+                            .err_at(col, "A '.' requires a following builtin name")
+                            .with_code("E0101")
+                            .with_help(
+                                "builtins are `.const <name> <value>` and `.byte <value>`",
+                            ));
                     }
                     let start = self.pos;
                     while self.cur().is_some_and(|b| b.is_ascii_alphabetic()) {
                         self.advance()
                     }
-                    toks.push(self.tok(TokenInner::Builtin(&self.src[start..self.pos])))
+                    toks.push(self.tok_at(col, TokenInner::Builtin(&self.src[start..self.pos])))
                 }
                 '#' => {
                     toks.push(self.tok(TokenInner::Hash));
@@ -122,6 +168,7 @@ impl<'lex> Lexer<'lex> {
                     self.advance()
                 }
                 '0'..='9' => {
+                    let col = self.col;
                     let start = self.pos;
                     while self
                         .cur()
@@ -130,28 +177,42 @@ impl<'lex> Lexer<'lex> {
                         self.advance()
                     }
                     let view = &self.src[start..self.pos];
-                    let as_str = str::from_utf8(view)
-                        .map_err(|_| self.err("Failed to call str::from_utf8"))?;
+                    let as_str = str::from_utf8(view).map_err(|_| {
+                        self.err_at(col, "Failed to call str::from_utf8")
+                            .with_code("E0102")
+                    })?;
                     let i = if view.get(1).is_some_and(|e| *e == b'x') {
                         u8::from_str_radix(&as_str[2..as_str.len()], 16)
                     } else {
                         as_str.parse()
                     }
-                    .map_err(|e| self.err(format!("{e}: `{as_str}`")))?;
-                    toks.push(self.tok(TokenInner::Number(i)))
+                    .map_err(|e| {
+                        self.err_at(col, format!("{e}: `{as_str}`"))
+                            .with_code("E0102")
+                            .with_help(
+                                "numbers are decimal or uppercase hex prefixed by 0x, up to 255",
+                            )
+                    })?;
+                    toks.push(self.tok_at(col, TokenInner::Number(i)))
                 }
                 'a'..='z' | 'A'..='Z' => {
+                    let col = self.col;
                     let start = self.pos;
-                    while self.cur().is_some_and(|b| b.is_ascii_alphabetic()) {
+                    while self
+                        .cur()
+                        .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+                    {
                         self.advance()
                     }
-                    toks.push(self.tok(TokenInner::Ident(&self.src[start..self.pos])))
+                    toks.push(self.tok_at(col, TokenInner::Ident(&self.src[start..self.pos])))
                 }
                 _ => {
-                    return Err(self.err(format!(
-                        "Unkown character `{}`",
-                        self.cur().map(|b| *b as char).unwrap(),
-                    )));
+                    return Err(self
+                        .err(format!(
+                            "Unkown character `{}`",
+                            self.cur().map(|b| *b as char).unwrap(),
+                        ))
+                        .with_code("E0103"));
                 }
             }
         }
@@ -246,4 +307,88 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_lexer_columns_start_at_zero_on_every_line() {
+        let tokens = Lexer::new(b"HALT\n.const led 0xF\nST [led]")
+            .lex()
+            .expect("Lexer failed");
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.line, t.col, t.len))
+                .collect::<Vec<_>>(),
+            [
+                (0, 0, 4),
+                (1, 0, 6),
+                (1, 7, 3),
+                (1, 11, 3),
+                (2, 0, 2),
+                (2, 3, 1),
+                (2, 4, 3),
+                (2, 7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_comments() {
+        let tokens = Lexer::new(b"; top\r\n\tHALT ; end  \r\n")
+            .with_comments()
+            .lex()
+            .expect("Lexer failed");
+        assert_eq!(
+            tokens,
+            vec![
+                Token {
+                    line: 0,
+                    col: 0,
+                    len: 5,
+                    inner: TokenInner::Comment(b"; top"),
+                },
+                Token {
+                    line: 1,
+                    col: 1,
+                    len: 4,
+                    inner: TokenInner::Ident(b"HALT"),
+                },
+                Token {
+                    line: 1,
+                    col: 6,
+                    len: 5,
+                    inner: TokenInner::Comment(b"; end"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_ident_with_digits() {
+        let tokens = Lexer::new(b".const val_5 5").lex().expect("Lexer failed");
+        assert_eq!(tokens[1].inner, TokenInner::Ident(b"val_5"));
+        assert_eq!(tokens[2].inner, TokenInner::Number(5));
+    }
+
+    #[test]
+    fn test_lexer_tab_and_carriage_return() {
+        let tokens = Lexer::new(b"\tHALT\r\n\tNOP\r\n")
+            .lex()
+            .expect("Lexer failed");
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|t| (t.line, t.col, t.inner))
+                .collect::<Vec<_>>(),
+            [
+                (0, 1, TokenInner::Ident(b"HALT")),
+                (1, 1, TokenInner::Ident(b"NOP")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_fail_ident_starting_with_underscore() {
+        let err = Lexer::new(b"_val").lex().unwrap_err();
+        assert_eq!(err.code, Some("E0103"));
+    }
 }
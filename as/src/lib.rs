@@ -0,0 +1,112 @@
+use shared::{debug::DebugInfo, err::T8Err, scriptorium::Word};
+
+use crate::interop::Ctx;
+
+/// lowering of the ast to t8 instructions
+pub mod interop;
+/// tokenizing .t8 source
+pub mod lexer;
+/// warnings about likely mistakes that still assemble
+pub mod lint;
+/// parsing tokens into the ast
+pub mod parser;
+
+/// result of assembling a single source file
+#[derive(Debug)]
+pub struct Assembled {
+    pub words: Vec<Word>,
+    pub debug: DebugInfo,
+}
+
+impl Assembled {
+    /// Peephole optimizes every run of instructions between `.byte`s, see
+    /// [`shared::opt::optimize_indexes`]. Locations of removed instructions are dropped from the
+    /// debug info, the remaining ones are moved to their new pc.
+    pub fn optimize(&mut self) {
+        let mut keep = vec![false; self.words.len()];
+        let mut start = 0;
+        while start < self.words.len() {
+            let run = self.words[start..]
+                .iter()
+                .map_while(|w| match w {
+                    Word::Instruction(i) => Some(i.clone()),
+                    Word::Byte(_) => None,
+                })
+                .collect::<Vec<_>>();
+            for i in shared::opt::optimize_indexes(&run) {
+                keep[start + i] = true;
+            }
+            // the `.byte` ending the run, if any, is kept verbatim
+            if let Some(byte) = keep.get_mut(start + run.len()) {
+                *byte = true;
+            }
+            start += run.len() + 1;
+        }
+
+        let mut pcs = vec![None; self.words.len()];
+        let mut pc = 0;
+        for (old, kept) in keep.iter().enumerate() {
+            if *kept {
+                pcs[old] = Some(pc);
+                pc += 1;
+            }
+        }
+        let mut kept = keep.iter();
+        self.words.retain(|_| *kept.next().unwrap());
+        self.debug
+            .locs
+            .retain_mut(|loc| match pcs.get(loc.pc).copied().flatten() {
+                Some(pc) => {
+                    loc.pc = pc;
+                    true
+                }
+                None => false,
+            });
+    }
+}
+
+/// lex, parse and lower `src` to words, `file` is recorded as the source of the returned debug
+/// info
+pub fn assemble(src: &[u8], file: &str) -> Result<Assembled, T8Err> {
+    let tokens = lexer::Lexer::new(src).lex()?;
+    let ast = parser::Parser::new(&tokens).parse()?;
+    let mut ctx = Ctx::new(file);
+    let mut words = Vec::with_capacity(ast.len());
+    for node in ast {
+        words.extend(ctx.node_to_words(node)?);
+    }
+    Ok(Assembled {
+        words,
+        debug: ctx.debug,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::asm::Instruction;
+
+    #[test]
+    fn test_optimize_remaps_debug_info() {
+        let mut assembled =
+            assemble(b"NOP\nLOADI #1\nLOADI #1\n.byte 0x9A\nNOP\nHALT", "o.t8").unwrap();
+        assembled.optimize();
+        assert_eq!(
+            assembled.words,
+            vec![
+                Word::Instruction(Instruction::LOADI { imm: 1 }),
+                Word::Byte(0x9A),
+                Word::Instruction(Instruction::HALT),
+            ]
+        );
+        assert_eq!(
+            assembled
+                .debug
+                .locs
+                .iter()
+                .map(|l| (l.pc, l.line))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (1, 3), (2, 5)]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use crate::{
+    lexer::{Lexer, TokenInner},
+    parser::{Builtin, Node, Parser},
+};
+use shared::{asm::Instruction, err::T8Err};
+
+pub const UNUSED_CONST: &str = "W0401";
+pub const UNREACHABLE: &str = "W0402";
+pub const UNINIT_LOAD: &str = "W0403";
+pub const UNMAPPED_STORE: &str = "W0404";
+pub const REDUNDANT_MOV: &str = "W0405";
+pub const NOOP_ROL: &str = "W0406";
+
+/// every lint as (id, description), any of them is silenced for a line via `; allow(<id>)` at
+/// its end or on the line before, several ids are separated by commas
+pub const LINTS: [(&str, &str); 6] = [
+    (UNUSED_CONST, "`.const` that is never used as an operand"),
+    (UNREACHABLE, "instructions after a HALT"),
+    (
+        UNINIT_LOAD,
+        "LD of an address that was never written and is not a mapped device",
+    ),
+    (
+        UNMAPPED_STORE,
+        "ST to an address that is not mapped in t8.toml and never read back",
+    ),
+    (REDUNDANT_MOV, "MOV while DEST already holds AC"),
+    (NOOP_ROL, "ROL #0 and ROL #8, both leave AC unchanged"),
+];
+
+/// Lines a lint is silenced on, collected from `; allow(W0401, W0405)` comments. The comment
+/// silences its own line, or the next one if it stands on a line of its own.
+fn allowed(src: &[u8]) -> Result<HashMap<usize, Vec<String>>, T8Err> {
+    let tokens = Lexer::new(src).with_comments().lex()?;
+    let mut allowed: HashMap<usize, Vec<String>> = HashMap::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        let TokenInner::Comment(text) = tok.inner else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(text);
+        let Some(ids) = text
+            .split_once("allow(")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .map(|(ids, _)| ids)
+        else {
+            continue;
+        };
+        let own_line = i == 0 || tokens[i - 1].line != tok.line;
+        let line = if own_line { tok.line + 1 } else { tok.line };
+        allowed
+            .entry(line)
+            .or_default()
+            .extend(ids.split(',').map(|id| id.trim().to_string()));
+    }
+    Ok(allowed)
+}
+
+/// value and name of an instruction operand, the value is `None` for undefined constants
+fn operand<'n>(node: &Node<'n>, constants: &HashMap<&str, u8>) -> (Option<u8>, Option<&'n str>) {
+    match node {
+        Node::Literal(inner) | Node::Addr(inner) => operand(inner, constants),
+        Node::Number(n) => (Some(*n), None),
+        Node::Ident { inner, .. } => (constants.get(inner).copied(), Some(inner)),
+        _ => (None, None),
+    }
+}
+
+fn lint_warning(
+    code: &'static str,
+    pos: (usize, usize),
+    len: usize,
+    msg: String,
+    help: String,
+) -> T8Err {
+    T8Err::warning(pos.0, pos.1, msg)
+        .with_len(len)
+        .with_code(code)
+        .with_help(help)
+}
+
+/// Lints `src`, see [`LINTS`]. `devices` are the addresses mapped in t8.toml, without them no
+/// address counts as mapped and [`UNMAPPED_STORE`], which would flag every store, is skipped.
+/// Errors are only returned if `src` does not parse.
+pub fn lint(src: &[u8], devices: Option<&[u8]>) -> Result<Vec<T8Err>, T8Err> {
+    let tokens = Lexer::new(src).lex()?;
+    let ast = Parser::new(&tokens).parse()?;
+
+    let mut warnings = vec![];
+    let mut constants = HashMap::new();
+    // every definition as (name, pos, used), in order of definition
+    let mut defs: Vec<(&str, (usize, usize), bool)> = vec![];
+    let mut halt: Option<(usize, usize)> = None;
+    let mut unreachable_reported = false;
+    let mut written = [false; 256];
+    // stores to unmapped addresses not yet read back, as (addr, pos)
+    let mut stores: Vec<(u8, (usize, usize))> = vec![];
+    // position of the MOV DEST was last set by, cleared once AC changes
+    let mut last_mov: Option<(usize, usize)> = None;
+
+    for node in &ast {
+        let (pos, len) = match node {
+            Node::Builtin {
+                pos,
+                kind: Builtin::Const,
+                lhs,
+                rhs,
+            } => {
+                if let Node::Number(n) = **rhs {
+                    constants.insert(*lhs, n);
+                }
+                defs.push((lhs, *pos, false));
+                continue;
+            }
+            Node::Instruction { pos, partial, .. } => (*pos, partial.to_str_lossy().len()),
+            Node::Load { pos, .. } => (*pos, ".load".len()),
+            _ => continue,
+        };
+
+        if let Some(halted) = halt
+            && !unreachable_reported
+        {
+            warnings.push(
+                lint_warning(
+                    UNREACHABLE,
+                    pos,
+                    len,
+                    "Unreachable instruction".into(),
+                    "remove the instructions after the HALT".into(),
+                )
+                .with_label(halted.0, halted.1, 4, "execution stops here"),
+            );
+            unreachable_reported = true;
+        }
+
+        let (partial, rhs) = match node {
+            Node::Instruction { partial, rhs, .. } => (partial, rhs),
+            Node::Load { value, keep, .. } => {
+                if let Node::Ident { inner, .. } = **value
+                    && let Some(def) = defs.iter_mut().rev().find(|d| d.0 == inner)
+                {
+                    def.2 = true;
+                }
+                // the sequence .load expands to may overwrite DEST unless it has to keep it
+                if !keep {
+                    last_mov = None;
+                }
+                continue;
+            }
+            _ => unreachable!(),
+        };
+
+        let (value, name) = rhs
+            .as_deref()
+            .map(|rhs| operand(rhs, &constants))
+            .unwrap_or_default();
+        if let Some(name) = name
+            && let Some(def) = defs.iter_mut().rev().find(|d| d.0 == name)
+        {
+            def.2 = true;
+        }
+
+        match (partial, value) {
+            (Instruction::HALT, _) => halt = halt.or(Some(pos)),
+            (Instruction::MOV, _) => {
+                if let Some(previous) = last_mov {
+                    warnings.push(
+                        lint_warning(
+                            REDUNDANT_MOV,
+                            pos,
+                            len,
+                            "Redundant MOV, DEST already holds AC".into(),
+                            "remove this MOV".into(),
+                        )
+                        .with_label(
+                            previous.0,
+                            previous.1,
+                            3,
+                            "DEST set here",
+                        ),
+                    );
+                }
+                last_mov = Some(pos);
+            }
+            (Instruction::ROL { .. }, Some(imm)) => {
+                if imm & 0x7 == 0 {
+                    warnings.push(lint_warning(
+                        NOOP_ROL,
+                        pos,
+                        len,
+                        format!("ROL #{imm} leaves AC unchanged"),
+                        "remove this ROL, only 1 to 7 rotate AC".into(),
+                    ));
+                } else {
+                    last_mov = None;
+                }
+            }
+            (Instruction::ST { .. }, Some(addr)) => {
+                written[addr as usize] = true;
+                if devices.is_some_and(|d| !d.contains(&addr)) {
+                    stores.push((addr, pos));
+                }
+            }
+            (Instruction::LD { .. }, Some(addr)) => {
+                stores.retain(|(a, _)| *a != addr);
+                if !written[addr as usize] && !devices.is_some_and(|d| d.contains(&addr)) {
+                    warnings.push(lint_warning(
+                        UNINIT_LOAD,
+                        pos,
+                        len,
+                        format!(
+                            "Load from 0x{addr:X}, which is never written and not mapped in t8.toml"
+                        ),
+                        format!("ST to 0x{addr:X} first, or map a device to it in t8.toml"),
+                    ));
+                }
+                last_mov = None;
+            }
+            (Instruction::NOP | Instruction::ST { .. }, _) => {}
+            _ => last_mov = None,
+        }
+    }
+
+    for (addr, pos) in stores {
+        warnings.push(lint_warning(
+            UNMAPPED_STORE,
+            pos,
+            2,
+            format!("Store to 0x{addr:X}, which is not mapped in t8.toml and never read back"),
+            format!("map a device to 0x{addr:X} in t8.toml, or remove the ST"),
+        ));
+    }
+    for (name, pos, _) in defs.into_iter().filter(|d| !d.2) {
+        warnings.push(lint_warning(
+            UNUSED_CONST,
+            pos,
+            ".const ".len() + name.len(),
+            format!("Constant `{name}` is never used"),
+            format!("remove the `.const` or use `{name}` as an operand"),
+        ));
+    }
+
+    let allowed = allowed(src)?;
+    warnings.retain(|w| {
+        !allowed
+            .get(&w.line)
+            .is_some_and(|ids| ids.iter().any(|id| Some(id.as_str()) == w.code))
+    });
+    warnings.sort_by_key(|w| (w.line, w.col));
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(src: &str, devices: Option<&[u8]>) -> Vec<(usize, &'static str)> {
+        lint(src.as_bytes(), devices)
+            .unwrap()
+            .into_iter()
+            .map(|w| (w.line, w.code.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lints() {
+        let src = "\
+.const unused 1
+.const led 0xF
+    LOADI #1
+    MOV
+    ST [led]
+    MOV
+    ROL #8
+    ST [5]
+    LD [6]
+    HALT
+    NOP
+";
+        assert_eq!(
+            codes(src, Some(&[0xF])),
+            vec![
+                (0, UNUSED_CONST),
+                (5, REDUNDANT_MOV),
+                (6, NOOP_ROL),
+                (7, UNMAPPED_STORE),
+                (8, UNINIT_LOAD),
+                (10, UNREACHABLE),
+            ]
+        );
+        assert_eq!(
+            codes(src, None),
+            vec![
+                (0, UNUSED_CONST),
+                (5, REDUNDANT_MOV),
+                (6, NOOP_ROL),
+                (8, UNINIT_LOAD),
+                (10, UNREACHABLE),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scratch_memory_is_fine() {
+        assert_eq!(
+            codes("LOADI #1\nST [5]\nLD [5]\nROL #4\nHALT", Some(&[])),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_allow_comments() {
+        let src = "\
+.const unused 1 ; allow(W0401)
+; allow(W0405, W0406)
+    ROL #0
+    ROL #0
+";
+        assert_eq!(codes(src, None), vec![(3, NOOP_ROL)]);
+    }
+
+    #[test]
+    fn test_help_names_the_fix() {
+        let warnings = lint(b"LD [6]\nHALT", None).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].help,
+            ["ST to 0x6 first, or map a device to it in t8.toml"]
+        );
+    }
+}
@@ -1,53 +1,71 @@
-#![allow(dead_code)]
-use std::{
-    fs,
-    io::{BufRead, stdout},
-    path::Path,
-};
+use std::{fs, io::BufRead, path::Path};
 
-use shared::scriptorium::Script;
-
-use crate::interop::Ctx;
-
-mod interop;
-mod lexer;
-mod parser;
+use shared::{config, err::MessageFormat, scriptorium::Script};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let input = std::env::args()
-        .nth(1)
-        .ok_or_else(|| "Missing .t8 asm file".to_string())?;
+    let mut input = None;
+    let mut format = MessageFormat::default();
+    let mut config = None;
+    let mut optimize = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-O" | "--optimize" => optimize = true,
+            "--config" => {
+                config = Some(
+                    args.next()
+                        .ok_or_else(|| "--config requires a path to a t8.toml".to_string())?,
+                )
+            }
+            _ => match MessageFormat::from_arg(&arg) {
+                Some(f) => format = f?,
+                None => input = Some(arg),
+            },
+        }
+    }
+    let input = input.ok_or_else(|| "Missing .t8 asm file".to_string())?;
+
+    // the memory map for the lints, without a t8.toml no address is mapped
+    let config = config.or_else(|| Path::new("t8.toml").exists().then(|| "t8.toml".into()));
+    let devices = match config {
+        Some(path) => {
+            let config = toml::from_slice::<config::Config>(&fs::read(&path)?)
+                .map_err(|e| format!("Failed to parse {path}: {e}"))?;
+            for warning in config.warnings() {
+                eprintln!("warning: {path}: {warning}");
+            }
+            Some(config.addrs())
+        }
+        None => None,
+    };
 
     let bytes = fs::read(&input)?;
-    let lines = bytes.lines().flatten().collect::<Vec<_>>();
+    let lines = bytes.lines().map_while(Result::ok).collect::<Vec<_>>();
 
-    let tokens = lexer::Lexer::new(&bytes).lex().map_err(|e| {
-        let _ = e.render(&mut stdout(), &lines);
-        "Failed to tokenize"
+    let mut assembled = r#as::assemble(&bytes, &input).map_err(|e| {
+        let _ = format.emit(&e, &input, &lines);
+        "Failed to assemble"
     })?;
+    // assembling succeeded, so the source parses and linting can not fail
+    let lints = r#as::lint::lint(&bytes, devices.as_deref()).unwrap_or_default();
+    for warning in &lints {
+        format.emit(warning, &input, &lines)?;
+    }
 
-    let ast = parser::Parser::new(&tokens).parse().map_err(|e| {
-        let _ = e.render(&mut stdout(), &lines);
-        "Failed to parse"
-    })?;
+    if optimize {
+        assembled.optimize();
+    }
 
     let mut buf = Vec::with_capacity(256);
-    let mut ctx = Ctx::new();
-    Script::new(&mut buf)?.add_instructions(
-        &ast.into_iter()
-            .flat_map(|n| {
-                ctx.node_to_instruction(n)
-                    .map_err(|e| {
-                        e.render(&mut stdout(), &lines).unwrap();
-                    })
-                    .expect("Failed to lower ast to instructions")
-            })
-            .collect::<Vec<_>>(),
-    )?;
+    Script::new(&mut buf)?.add_words(&assembled.words)?;
 
     let mut path = Path::new(&input).to_path_buf();
     path.set_extension("t8b");
-    fs::write(path, &buf)?;
+    fs::write(&path, &buf)?;
+
+    let mut sidecar = Vec::new();
+    assembled.debug.write(&mut sidecar)?;
+    fs::write(shared::debug::sidecar(&path), &sidecar)?;
 
     Ok(())
 }
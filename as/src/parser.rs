@@ -4,6 +4,8 @@ use shared::{asm::Instruction, err::T8Err};
 #[derive(Debug, PartialEq, Eq)]
 pub enum Builtin {
     Const,
+    Byte,
+    Load,
 }
 
 impl TryFrom<&[u8]> for Builtin {
@@ -12,6 +14,8 @@ impl TryFrom<&[u8]> for Builtin {
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
         match value {
             b"const" => Ok(Self::Const),
+            b"byte" => Ok(Self::Byte),
+            b"load" => Ok(Self::Load),
             _ => Err(format!(
                 "Unknown builtin `{}`",
                 String::from_utf8_lossy(value)
@@ -24,12 +28,26 @@ impl TryFrom<&[u8]> for Builtin {
 pub enum Node<'node> {
     /// .<kind> <lhs> <rhs>
     Builtin {
+        pos: (usize, usize),
         kind: Builtin,
         lhs: &'node str,
         rhs: Box<Node<'node>>,
     },
+    /// .byte <value>, emitted verbatim
+    Byte {
+        pos: (usize, usize),
+        value: u8,
+    },
+    /// .load <value> [keep], the shortest instruction sequence leaving the 8 bit value in AC,
+    /// with `keep` one that leaves DEST intact
+    Load {
+        pos: (usize, usize),
+        value: Box<Node<'node>>,
+        keep: bool,
+    },
     /// <instruction> <rhs>
     Instruction {
+        pos: (usize, usize),
         /// partial since this does not include inner values, only the name -> instruction lookup
         /// is done at this point
         partial: Instruction,
@@ -51,9 +69,14 @@ pub struct Parser<'parser> {
     pos: usize,
 }
 
+/// current token, returns an `Unexpected end of input` error from the enclosing function if there
+/// is none
 macro_rules! cur {
     ($l:ident) => {
-        $l.src[$l.pos]
+        match $l.src.get($l.pos) {
+            Some(tok) => *tok,
+            None => return Err($l.eof()),
+        }
     };
 }
 
@@ -73,34 +96,81 @@ impl<'parser> Parser<'parser> {
     }
 
     fn err<S: Into<String>>(&self, msg: S) -> T8Err {
-        let Token { line, col, .. } = cur!(self);
-        T8Err {
-            line,
-            col,
-            msg: msg.into(),
+        match self.src.get(self.pos) {
+            Some(Token { line, col, len, .. }) => T8Err::new(*line, *col, msg).with_len(*len),
+            None => self.eof(),
         }
     }
 
+    fn eof(&self) -> T8Err {
+        let (line, col) = self
+            .src
+            .last()
+            .map(|l| (l.line, l.col + l.len))
+            .unwrap_or_else(|| (0, 0));
+
+        T8Err::new(line, col, "Unexpected end of input").with_code("E0201")
+    }
+
     fn parse_one(&mut self) -> Result<Node<'parser>, T8Err> {
         if self.end() {
-            let (line, col) = self
-                .src
-                .last()
-                .map(|l| (l.line, l.col))
-                .unwrap_or_else(|| (0, 0));
-
-            return Err(T8Err {
-                line,
-                col,
-                msg: "Unexpected end of input".into(),
-            });
+            return Err(self.eof());
         }
-        let Token { inner, line, col } = cur!(self);
+        let Token {
+            inner, line, col, ..
+        } = cur!(self);
         Ok(match inner {
             TokenInner::Builtin(name) => {
-                let kind = (*name).try_into().map_err(|e| self.err(e))?;
+                let kind = (*name)
+                    .try_into()
+                    .map_err(|e| self.err(e).with_code("E0202"))?;
                 // skip .<kind>
                 advance!(self);
+
+                if kind == Builtin::Byte {
+                    let TokenInner::Number(value) = cur!(self).inner else {
+                        return Err(self
+                            .err("Invalid rhs for .byte, wanted number")
+                            .with_code("E0203"));
+                    };
+                    // skip value
+                    advance!(self);
+                    return Ok(Node::Byte {
+                        pos: (line, col),
+                        value,
+                    });
+                }
+
+                if kind == Builtin::Load {
+                    let tok = cur!(self);
+                    let value = match tok.inner {
+                        TokenInner::Number(n) => Node::Number(n),
+                        TokenInner::Ident(ident) => Node::Ident {
+                            pos: (tok.line, tok.col),
+                            inner: str::from_utf8(ident).unwrap(),
+                        },
+                        _ => {
+                            return Err(self
+                                .err("Invalid rhs for .load, wanted number or constant")
+                                .with_code("E0203"));
+                        }
+                    };
+                    // skip value
+                    advance!(self);
+                    let keep = self
+                        .src
+                        .get(self.pos)
+                        .is_some_and(|t| t.line == line && t.inner == TokenInner::Ident(b"keep"));
+                    if keep {
+                        advance!(self);
+                    }
+                    return Ok(Node::Load {
+                        pos: (line, col),
+                        value: Box::new(value),
+                        keep,
+                    });
+                }
+
                 let lhs = if let Token {
                     inner: TokenInner::Ident(lhs),
                     ..
@@ -108,7 +178,9 @@ impl<'parser> Parser<'parser> {
                 {
                     str::from_utf8(lhs).unwrap()
                 } else {
-                    return Err(self.err("Wanted ident as builtin lhs, got something else"));
+                    return Err(self
+                        .err("Wanted ident as builtin lhs, got something else")
+                        .with_code("E0203"));
                 };
                 // skip lhs
                 advance!(self);
@@ -117,23 +189,36 @@ impl<'parser> Parser<'parser> {
                     Builtin::Const => match cur!(self).inner {
                         TokenInner::Number(n) => Node::Number(n),
                         _ => {
-                            return Err(self.err("Invalid rhs for .const, wanted number"));
+                            return Err(self
+                                .err("Invalid rhs for .const, wanted number")
+                                .with_code("E0203"));
                         }
                     },
+                    Builtin::Byte | Builtin::Load => unreachable!(),
                 };
 
                 // skip argument
                 advance!(self);
 
                 Node::Builtin {
+                    pos: (line, col),
                     kind,
                     lhs,
                     rhs: Box::new(rhs),
                 }
             }
             TokenInner::Ident(ident) => {
-                let partial = Instruction::from_str_lossy(str::from_utf8(ident).unwrap())
-                    .map_err(|e| self.err(e))?;
+                // mnemonics are case insensitive, t8fmt uppercases them
+                let partial = Instruction::from_str_lossy(
+                    &str::from_utf8(ident).unwrap().to_ascii_uppercase(),
+                )
+                .map_err(|_| {
+                    self.err(format!(
+                        "Invalid instruction `{}`",
+                        String::from_utf8_lossy(ident)
+                    ))
+                    .with_code("E0204")
+                })?;
                 // skip self
                 advance!(self);
 
@@ -149,7 +234,11 @@ impl<'parser> Parser<'parser> {
                     | Instruction::SUB
                     | Instruction::HALT => None,
                 };
-                Node::Instruction { partial, rhs }
+                Node::Instruction {
+                    pos: (line, col),
+                    partial,
+                    rhs,
+                }
             }
             TokenInner::Hash => {
                 // skip #
@@ -161,7 +250,9 @@ impl<'parser> Parser<'parser> {
                         inner: str::from_utf8(ident).unwrap(),
                     },
                     _ => {
-                        return Err(self.err("Invalid inner literal, wanted ident or number"));
+                        return Err(self
+                            .err("Invalid inner literal, wanted ident or number")
+                            .with_code("E0205"));
                     }
                 };
                 // skip number or ident
@@ -178,30 +269,28 @@ impl<'parser> Parser<'parser> {
                         inner: str::from_utf8(ident).unwrap(),
                     },
                     _ => {
-                        return Err(self.err("Invalid inner addr, wanted ident or number"));
+                        return Err(self
+                            .err("Invalid inner addr, wanted ident or number")
+                            .with_code("E0205"));
                     }
                 };
 
-                let Token { line, col, .. } = cur!(self);
+                let Token { line, col, len, .. } = cur!(self);
 
                 // skip inner
                 advance!(self);
 
                 let addr = Node::Addr(Box::new(inner));
-                if self.src.get(self.pos)
-                    != Some(&Token {
-                        inner: TokenInner::RightBraket,
-                        line,
-                        col,
-                    })
+                if !self
+                    .src
+                    .get(self.pos)
+                    .is_some_and(|t| t.inner == TokenInner::RightBraket)
                 {
-                    return Err({
-                        T8Err {
-                            line,
-                            col,
-                            msg: "`]` postfix needed for addr syntax".into(),
-                        }
-                    });
+                    return Err(
+                        T8Err::new(line, col + len, "`]` postfix needed for addr syntax")
+                            .with_code("E0206")
+                            .with_help("addresses are written as `[<number or constant>]`"),
+                    );
                 }
 
                 // skip ]
@@ -212,7 +301,11 @@ impl<'parser> Parser<'parser> {
                 advance!(self);
                 Node::Number(n)
             }
-            _ => return Err(self.err(format!("Unkown token type {:?}", cur!(self)))),
+            _ => {
+                return Err(self
+                    .err(format!("Unkown token type {:?}", cur!(self)))
+                    .with_code("E0205"));
+            }
         })
     }
 
@@ -242,6 +335,7 @@ mod tests {
         assert_eq!(
             ast,
             vec![Node::Builtin {
+                pos: (0, 0),
                 kind: Builtin::Const,
                 lhs: "led",
                 rhs: Box::new(Node::Number(5)),
@@ -259,6 +353,7 @@ mod tests {
         assert_eq!(
             ast,
             vec![Node::Instruction {
+                pos: (0, 0),
                 partial: Instruction::LOADI { imm: 0 },
                 rhs: Some(Box::new(Node::Literal(Box::new(Node::Ident {
                     pos: (0, 6),
@@ -276,6 +371,7 @@ mod tests {
         assert_eq!(
             ast,
             vec![Node::Instruction {
+                pos: (0, 0),
                 partial: Instruction::LOADI { imm: 0 },
                 rhs: Some(Box::new(Node::Literal(Box::new(Node::Number(3))))),
             }]
@@ -290,6 +386,7 @@ mod tests {
         assert_eq!(
             ast,
             vec![Node::Instruction {
+                pos: (0, 0),
                 partial: Instruction::ST { addr: 0 },
                 rhs: Some(Box::new(Node::Addr(Box::new(Node::Ident {
                     pos: (0, 3),
@@ -307,6 +404,7 @@ mod tests {
         assert_eq!(
             ast,
             vec![Node::Instruction {
+                pos: (0, 0),
                 partial: Instruction::ST { addr: 0 },
                 rhs: Some(Box::new(Node::Addr(Box::new(Node::Number(5))))),
             }]
@@ -329,25 +427,52 @@ HALT
             ast,
             vec![
                 Node::Builtin {
+                    pos: (1, 0),
                     kind: Builtin::Const,
                     lhs: "led",
                     rhs: Box::new(Node::Number(0xF)),
                 },
                 Node::Instruction {
+                    pos: (2, 0),
                     partial: Instruction::LOADI { imm: 0 },
                     rhs: Some(Box::new(Node::Literal(Box::new(Node::Ident {
-                        pos: (2, 7),
+                        pos: (2, 6),
                         inner: "led"
                     })))),
                 },
                 Node::Instruction {
+                    pos: (3, 0),
                     partial: Instruction::ST { addr: 0 },
                     rhs: Some(Box::new(Node::Addr(Box::new(Node::Ident {
-                        pos: (3, 4),
+                        pos: (3, 3),
                         inner: "led"
                     })))),
                 },
                 Node::Instruction {
+                    pos: (4, 0),
+                    partial: Instruction::HALT,
+                    rhs: None,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_byte_builtin() {
+        let tokens = Lexer::new(".byte 0x9A\nHALT".as_bytes())
+            .lex()
+            .expect("lex failed");
+        let ast = Parser::new(&tokens).parse().expect("parse failed");
+
+        assert_eq!(
+            ast,
+            vec![
+                Node::Byte {
+                    pos: (0, 0),
+                    value: 0x9A,
+                },
+                Node::Instruction {
+                    pos: (1, 0),
                     partial: Instruction::HALT,
                     rhs: None,
                 }
@@ -355,12 +480,74 @@ HALT
         );
     }
 
+    #[test]
+    fn test_load_builtin() {
+        let tokens = Lexer::new(".load 0x48\n.load big keep\nkeep".as_bytes())
+            .lex()
+            .expect("lex failed");
+        let err = Parser::new(&tokens).parse().unwrap_err();
+        // `keep` only belongs to a `.load` on the same line
+        assert_eq!((err.line, err.code), (2, Some("E0204")));
+        let ast = Parser::new(&tokens[..tokens.len() - 1])
+            .parse()
+            .expect("parse failed");
+
+        assert_eq!(
+            ast,
+            vec![
+                Node::Load {
+                    pos: (0, 0),
+                    value: Box::new(Node::Number(0x48)),
+                    keep: false,
+                },
+                Node::Load {
+                    pos: (1, 0),
+                    value: Box::new(Node::Ident {
+                        pos: (1, 6),
+                        inner: "big",
+                    }),
+                    keep: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lowercase_mnemonic() {
+        let tokens = Lexer::new("halt".as_bytes()).lex().expect("lex failed");
+        let ast = Parser::new(&tokens).parse().expect("parse failed");
+        assert_eq!(
+            ast,
+            vec![Node::Instruction {
+                pos: (0, 0),
+                partial: Instruction::HALT,
+                rhs: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fail_unknown_mnemonic_keeps_case() {
+        let tokens = Lexer::new("Jmpx".as_bytes()).lex().expect("lex failed");
+        let err = Parser::new(&tokens).parse().unwrap_err();
+        assert_eq!(err.msg, "Invalid instruction `Jmpx`");
+        assert_eq!(err.code, Some("E0204"));
+    }
+
     #[test]
     fn test_fail_missing_rhs() {
         let tokens = Lexer::new("LOADI".as_bytes()).lex().expect("lex failed");
         assert!(Parser::new(&tokens).parse().is_err());
     }
 
+    #[test]
+    fn test_fail_truncated_input() {
+        for src in [".const x", ".byte", "LOADI #", "ST [", "ST [led"] {
+            let tokens = Lexer::new(src.as_bytes()).lex().expect("lex failed");
+            assert!(Parser::new(&tokens).parse().is_err(), "{src}");
+        }
+    }
+
     #[test]
     fn test_fail_addr_missing_bracket() {
         let tokens = Lexer::new("ST [led".as_bytes()).lex().expect("lex failed");
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use shared::{
+    asm::Instruction,
+    err::T8Err,
+    superopt::{self, Constraints},
+};
+
+/// an s-expression, `pos` is the zero based line and column of its first character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr<'src> {
+    Atom {
+        pos: (usize, usize),
+        text: &'src str,
+    },
+    Str {
+        pos: (usize, usize),
+        text: &'src str,
+    },
+    List {
+        pos: (usize, usize),
+        items: Vec<Expr<'src>>,
+    },
+}
+
+impl Expr<'_> {
+    fn pos(&self) -> (usize, usize) {
+        match self {
+            Expr::Atom { pos, .. } | Expr::Str { pos, .. } | Expr::List { pos, .. } => *pos,
+        }
+    }
+
+    /// characters underlined when pointing at the expression, lists only mark their `(`
+    fn len(&self) -> usize {
+        match self {
+            Expr::Atom { text, .. } => text.chars().count(),
+            Expr::Str { text, .. } => text.chars().count() + 2,
+            Expr::List { .. } => 1,
+        }
+    }
+
+    fn err<S: Into<String>>(&self, msg: S) -> T8Err {
+        let (line, col) = self.pos();
+        T8Err::new(line, col, msg).with_len(self.len())
+    }
+}
+
+/// Reads every top level expression of `src`, `;` starts a comment running to the end of the
+/// line.
+pub fn read(src: &str) -> Result<Vec<Expr<'_>>, T8Err> {
+    let mut chars = src.char_indices().peekable();
+    let (mut line, mut col) = (0, 0);
+    // open lists with the position of their `(`
+    let mut stack: Vec<((usize, usize), Vec<Expr>)> = vec![];
+    let mut top = vec![];
+
+    while let Some((start, c)) = chars.next() {
+        let pos = (line, col);
+        col += 1;
+        let expr = match c {
+            '\n' => {
+                line += 1;
+                col = 0;
+                continue;
+            }
+            ';' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '(' => {
+                stack.push((pos, vec![]));
+                continue;
+            }
+            ')' => match stack.pop() {
+                Some((pos, items)) => Expr::List { pos, items },
+                None => {
+                    return Err(T8Err::new(pos.0, pos.1, "Unmatched `)`").with_code("E0003"));
+                }
+            },
+            '"' => {
+                let mut end = None;
+                for (i, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                    col += 1;
+                    if c == '"' {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                let Some(end) = end else {
+                    return Err(T8Err::new(pos.0, pos.1, "Unterminated string").with_code("E0003"));
+                };
+                Expr::Str {
+                    pos,
+                    text: &src[start + 1..end],
+                }
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| !c.is_whitespace() && !"();\"".contains(*c))
+                {
+                    end = i + c.len_utf8();
+                    col += 1;
+                }
+                Expr::Atom {
+                    pos,
+                    text: &src[start..end],
+                }
+            }
+        };
+        match stack.last_mut() {
+            Some((_, items)) => items.push(expr),
+            None => top.push(expr),
+        }
+    }
+
+    match stack.pop() {
+        Some((pos, _)) => Err(T8Err::new(pos.0, pos.1, "Unclosed `(`")
+            .with_code("E0003")
+            .with_help("add a `)` closing the list")),
+        None => Ok(top),
+    }
+}
+
+/// parses `0x`/`0b` prefixed and decimal numbers
+fn number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// lowering of the expressions to t8 instructions
+#[derive(Debug, Default)]
+pub struct Compiler<'src> {
+    constants: HashMap<&'src str, u8>,
+    /// DEST while the emitted instructions run, `None` until the first MOV
+    dest: Option<u8>,
+    pub ins: Vec<Instruction>,
+}
+
+impl<'src> Compiler<'src> {
+    /// Lowers every form of `exprs` in order and ends the program with HALT:
+    ///
+    /// - `(const <name> <value>)` defines a constant resolved at compile time
+    /// - `(write <addr> <value>)` stores the value at the address, a string stores each byte
+    /// - `(repeat <n> <form>..)` unrolls the forms `n` times
+    pub fn compile(mut self, exprs: &[Expr<'src>]) -> Result<Vec<Instruction>, T8Err> {
+        for expr in exprs {
+            self.form(expr)?;
+        }
+        self.ins.push(Instruction::HALT);
+        Ok(self.ins)
+    }
+
+    fn form(&mut self, expr: &Expr<'src>) -> Result<(), T8Err> {
+        let Expr::List { items, .. } = expr else {
+            return Err(expr
+                .err("Expected a form")
+                .with_code("E0004")
+                .with_help("forms are lists like `(write out 1)`"));
+        };
+        let Some((Expr::Atom { text: name, .. }, args)) = items.split_first() else {
+            return Err(expr.err("Expected a form name").with_code("E0004"));
+        };
+        let arity = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(expr
+                    .err(format!(
+                        "`{name}` takes {n} arguments but {} were given",
+                        args.len()
+                    ))
+                    .with_code("E0004"))
+            }
+        };
+        match *name {
+            "const" => {
+                arity(2)?;
+                let Expr::Atom { text, .. } = &args[0] else {
+                    return Err(args[0].err("Expected a constant name").with_code("E0004"));
+                };
+                let value = match self.value(&args[1])?.as_slice() {
+                    [value] => *value,
+                    _ => {
+                        return Err(args[1].err("Expected a single byte").with_code("E0302"));
+                    }
+                };
+                self.constants.insert(text, value);
+            }
+            "write" => {
+                arity(2)?;
+                let addr = self.addr(&args[0])?;
+                for value in self.value(&args[1])? {
+                    self.load(value);
+                    self.ins.push(Instruction::ST { addr });
+                }
+            }
+            "repeat" => {
+                let Some((count, body)) = args.split_first() else {
+                    return Err(expr
+                        .err("`repeat` takes a count and the forms to repeat")
+                        .with_code("E0004"));
+                };
+                let count = match self.value(count)?.as_slice() {
+                    [count] => *count,
+                    _ => return Err(count.err("Expected a count").with_code("E0302")),
+                };
+                for _ in 0..count {
+                    for form in body {
+                        self.form(form)?;
+                    }
+                }
+            }
+            _ => {
+                return Err(items[0]
+                    .err(format!("Unknown form `{name}`"))
+                    .with_code("E0004")
+                    .with_help("known forms are `const`, `write` and `repeat`"));
+            }
+        }
+        Ok(())
+    }
+
+    /// bytes an argument stands for, a single one unless it is a string
+    fn value(&self, expr: &Expr<'src>) -> Result<Vec<u8>, T8Err> {
+        match expr {
+            Expr::Str { text, .. } => text
+                .chars()
+                .map(|c| u8::try_from(c).ok())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    expr.err("String contains a character wider than a byte")
+                        .with_code("E0302")
+                }),
+            Expr::Atom { text, .. } => match number(text) {
+                Some(n) => u8::try_from(n).map(|n| vec![n]).map_err(|_| {
+                    expr.err(format!("{text} does not fit into a byte"))
+                        .with_code("E0302")
+                }),
+                None => self.constants.get(text).map(|v| vec![*v]).ok_or_else(|| {
+                    expr.err(format!("Undefined constant `{text}`"))
+                        .with_code("E0301")
+                }),
+            },
+            Expr::List { .. } => Err(expr.err("Expected a value").with_code("E0004")),
+        }
+    }
+
+    /// address written to, spelled as a value with an optional `&` prefix
+    fn addr(&self, expr: &Expr<'src>) -> Result<u8, T8Err> {
+        let stripped = match expr {
+            Expr::Atom { pos, text } => Expr::Atom {
+                pos: *pos,
+                text: text.strip_prefix('&').unwrap_or(text),
+            },
+            _ => expr.clone(),
+        };
+        match self.value(&stripped)?.as_slice() {
+            [addr @ 0..=0xF] => Ok(*addr),
+            _ => Err(expr
+                .err("Address does not fit into 4 bits")
+                .with_code("E0302")
+                .with_help("only the memory at 0x0 to 0xF can be written")),
+        }
+    }
+
+    /// Emits the shortest sequence leaving `value` in AC, see [`superopt::materialize`], DEST
+    /// is known after the first MOV since nothing but the emitted instructions changes it.
+    fn load(&mut self, value: u8) {
+        let seq = superopt::materialize(
+            value,
+            Constraints {
+                dest: self.dest,
+                preserve_dest: false,
+            },
+        )
+        .expect("every value is reachable if DEST may be overwritten");
+        let mut ac = None;
+        for ins in &seq {
+            ac = match *ins {
+                Instruction::LOADI { imm } => Some(imm),
+                Instruction::ROL { imm } => ac.map(|ac: u8| ac.rotate_left(imm as u32)),
+                Instruction::ADD => self.dest.zip(ac).map(|(d, a)| d.wrapping_add(a)),
+                Instruction::SUB => self.dest.zip(ac).map(|(d, a)| d.wrapping_sub(a)),
+                Instruction::MOV => {
+                    self.dest = ac;
+                    ac
+                }
+                _ => ac,
+            };
+        }
+        self.ins.extend(seq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Instruction::*;
+
+    fn compile(src: &str) -> Result<Vec<Instruction>, T8Err> {
+        Compiler::default().compile(&read(src)?)
+    }
+
+    #[test]
+    fn reads_nested_lists() {
+        let exprs = read("; comment\n(write &led \"hi\") (a (b))").unwrap();
+        assert_eq!(exprs.len(), 2);
+        assert_eq!(
+            exprs[0],
+            Expr::List {
+                pos: (1, 0),
+                items: vec![
+                    Expr::Atom {
+                        pos: (1, 1),
+                        text: "write"
+                    },
+                    Expr::Atom {
+                        pos: (1, 7),
+                        text: "&led"
+                    },
+                    Expr::Str {
+                        pos: (1, 12),
+                        text: "hi"
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn reports_unbalanced_lists() {
+        let err = read("(write 1 2").unwrap_err();
+        assert_eq!((err.line, err.col, err.code), (0, 0, Some("E0003")));
+        let err = read("\n  (a))").unwrap_err();
+        assert_eq!((err.line, err.col, err.code), (1, 5, Some("E0003")));
+    }
+
+    #[test]
+    fn compiles_led() {
+        let src = "(const led 0xF) (const on 1) (repeat 2 (write &led on) (write &led 0))";
+        assert_eq!(
+            compile(src).unwrap(),
+            vec![
+                LOADI { imm: 1 },
+                ST { addr: 0xF },
+                LOADI { imm: 0 },
+                ST { addr: 0xF },
+                LOADI { imm: 1 },
+                ST { addr: 0xF },
+                LOADI { imm: 0 },
+                ST { addr: 0xF },
+                HALT,
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_wide_values_via_the_superoptimizer() {
+        let ins = compile("(const out 0) (write out \"HE\")").unwrap();
+        assert_eq!(ins[..3], [LOADI { imm: 9 }, ROL { imm: 3 }, ST { addr: 0 }]);
+        // 0x45 is no rotation of a 4 bit value, it takes DEST
+        assert!(ins[3..].contains(&MOV));
+        assert_eq!(ins[ins.len() - 2..], [ST { addr: 0 }, HALT]);
+    }
+
+    #[test]
+    fn tracks_dest_across_writes() {
+        // the load of 0x45 leaves DEST known, 0x4C builds on it
+        let ins = compile("(write 0 0x45) (write 0 0x4C)").unwrap();
+        let unknown = superopt::materialize(0x4C, Constraints::default()).unwrap();
+        let tail = &ins[ins.iter().position(|i| *i == ST { addr: 0 }).unwrap() + 1..];
+        assert!(tail.len() - 2 < unknown.len());
+    }
+
+    #[test]
+    fn optimizes_repeated_loads() {
+        // led.lisp writes 0 for both states, -O keeps a single LOADI
+        let ins = compile("(const led 0xF) (repeat 5 (write &led 0) (write &led 0))").unwrap();
+        let opt = shared::opt::optimize(&ins);
+        assert_eq!(opt.iter().filter(|i| **i == LOADI { imm: 0 }).count(), 1);
+        assert_eq!(opt.iter().filter(|i| **i == ST { addr: 0xF }).count(), 10);
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let err = compile("(write out 1)").unwrap_err();
+        assert_eq!((err.line, err.col, err.code), (0, 7, Some("E0301")));
+        let err = compile("(print 1)").unwrap_err();
+        assert_eq!((err.col, err.code), (1, Some("E0004")));
+        let err = compile("(write 0x10 1)").unwrap_err();
+        assert_eq!(err.code, Some("E0302"));
+    }
+}
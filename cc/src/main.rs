@@ -1,13 +1,61 @@
 use std::{fs, path::Path};
 
+use shared::{
+    err::{MessageFormat, T8Err},
+    scriptorium::Script,
+};
+
+/// reading and lowering of the lisp source
+mod compile;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let input = std::env::args()
-        .nth(1)
-        .ok_or_else(|| "Missing .lisp file".to_string())?;
-    let bytes = fs::read(&input)?;
+    let mut input = None;
+    let mut format = MessageFormat::default();
+    let mut optimize = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-O" | "--optimize" => optimize = true,
+            _ => match MessageFormat::from_arg(&arg) {
+                Some(f) => format = f?,
+                None => input = Some(arg),
+            },
+        }
+    }
+    let no_lines: &[&str] = &[];
+    let Some(input) = input else {
+        let err = T8Err::without_location("Missing .lisp file")
+            .with_code("E0001")
+            .with_help("pass the file to compile, for instance `cc examples/hello.lisp`");
+        format.emit(&err, "", no_lines)?;
+        return Err("Failed to compile".into());
+    };
+    let src = fs::read(&input)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            let err =
+                T8Err::without_location(format!("Failed to read {input}: {e}")).with_code("E0002");
+            let _ = format.emit(&err, &input, no_lines);
+            "Failed to compile"
+        })?;
+    let lines = src.lines().collect::<Vec<_>>();
+
+    let mut ins = compile::read(&src)
+        .and_then(|exprs| compile::Compiler::default().compile(&exprs))
+        .map_err(|e| {
+            let _ = format.emit(&e, &input, &lines);
+            "Failed to compile"
+        })?;
+    if optimize {
+        ins = shared::opt::optimize(&ins);
+    }
+
+    let mut buf = Vec::with_capacity(256);
+    Script::new(&mut buf)?.add_instructions(&ins)?;
+
     let mut path = Path::new(&input).to_path_buf();
     path.set_extension("t8b");
-    fs::write(path, &bytes)?;
+    fs::write(path, &buf)?;
 
     Ok(())
 }
@@ -1,51 +1,217 @@
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 
-use shared::asm::Instruction;
+use shared::{
+    asm::Instruction,
+    config,
+    debug::DebugInfo,
+    scriptorium::{Entry, ScriptReader},
+};
 
-fn dis(ins: &[Instruction]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+/// `devices` are (name, addr) pairs from t8.toml, used for `ST` and `LD` operands that have no
+/// symbol attached via debug info
+fn dis<R: std::io::Read>(
+    reader: ScriptReader<R>,
+    debug: Option<&DebugInfo>,
+    devices: &[(String, u8)],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let magic = reader.header().magic;
+    let entries = reader.collect::<Result<Vec<_>, _>>()?;
     let mut buf = Vec::new();
 
-    writeln!(
-        buf,
-        "; magic={}",
-        String::from_utf8_lossy(shared::scriptorium::MAGIC)
-    )?;
-
-    writeln!(buf, "; size={}\n", ins.len())?;
-    for (i, ins) in ins.iter().enumerate() {
-        if let Some(encoded) = ins.encode() {
-            writeln!(
-                buf,
-                "; {:04x}: 0x{:X} (op=0x{:X}, imm=0x{:X})",
-                i,
-                encoded,
-                ins.op(),
-                ins.imm(),
-            )?;
+    writeln!(buf, "; magic={}", String::from_utf8_lossy(&magic))?;
 
+    writeln!(buf, "; size={}\n", entries.len())?;
+
+    let symbols = debug.map(|d| d.symbols.as_slice()).unwrap_or_default();
+    for sym in symbols {
+        writeln!(buf, ".const {} 0x{:X}", sym.name, sym.value)?;
+    }
+    // a debug symbol with the same name takes precedence over the device
+    let devices = devices
+        .iter()
+        .filter(|(name, _)| !symbols.iter().any(|s| &s.name == name))
+        .collect::<Vec<_>>();
+    for (name, addr) in &devices {
+        writeln!(buf, ".const {name} 0x{addr:X}")?;
+    }
+    if !symbols.is_empty() || !devices.is_empty() {
+        writeln!(buf)?;
+    }
+
+    for Entry {
+        offset,
+        byte,
+        decoded,
+    } in entries
+    {
+        write!(buf, "; {:04x}: 0x{:02X}", offset, byte)?;
+        let ins = match &decoded {
+            Ok(ins) if ins.encode() == Some(byte) => ins,
+            // invalid opcodes, and operandless instructions with a non zero lower nibble, since
+            // their mnemonic would not reassemble to the same byte
+            _ => {
+                let what = decoded
+                    .as_ref()
+                    .map(|i| i.to_str_lossy())
+                    .unwrap_or("invalid");
+                writeln!(buf)?;
+                writeln!(buf, ".byte 0x{byte:02X} ; {what}")?;
+                continue;
+            }
+        };
+        write!(buf, " (op=0x{:X}, imm=0x{:X})", ins.op(), ins.imm())?;
+
+        let loc = debug.and_then(|d| Some((d, d.loc(offset)?)));
+        if let Some((debug, loc)) = loc {
+            write!(buf, " {}:{}:{}", debug.file, loc.line + 1, loc.col + 1)?;
+        }
+        writeln!(buf)?;
+
+        // only use the symbol if it still resolves to the encoded operand
+        let symbol = loc
+            .and_then(|(debug, loc)| debug.symbol(loc.operand.as_deref()?))
+            .filter(|sym| sym.value == ins.imm());
+        if let Some(sym) = symbol {
             match ins {
-                Instruction::NOP => writeln!(buf, "NOP")?,
-                Instruction::LOADI { imm } => writeln!(buf, "LOADI {imm}")?,
-                Instruction::MOV => writeln!(buf, "MOV")?,
-                Instruction::ADD => writeln!(buf, "ADD")?,
-                Instruction::SUB => writeln!(buf, "SUB")?,
-                Instruction::ST { addr } => writeln!(buf, "ST {addr}")?,
-                Instruction::LD { addr } => writeln!(buf, "LD {addr}")?,
-                Instruction::ROL { imm } => writeln!(buf, "ROL {imm}")?,
-                Instruction::HALT => writeln!(buf, "HALT")?,
-            };
+                Instruction::LOADI { .. } | Instruction::ROL { .. } => {
+                    writeln!(buf, "{} #{}", ins.to_str_lossy(), sym.name)?;
+                    continue;
+                }
+                Instruction::ST { .. } | Instruction::LD { .. } => {
+                    writeln!(buf, "{} [{}]", ins.to_str_lossy(), sym.name)?;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        if let Instruction::ST { addr } | Instruction::LD { addr } = ins
+            && let Some((name, _)) = devices.iter().find(|(_, a)| a == addr)
+        {
+            writeln!(buf, "{} [{}]", ins.to_str_lossy(), name)?;
+            continue;
         }
+
+        match ins {
+            Instruction::NOP => writeln!(buf, "NOP")?,
+            Instruction::LOADI { imm } => writeln!(buf, "LOADI #{imm}")?,
+            Instruction::MOV => writeln!(buf, "MOV")?,
+            Instruction::ADD => writeln!(buf, "ADD")?,
+            Instruction::SUB => writeln!(buf, "SUB")?,
+            Instruction::ST { addr } => writeln!(buf, "ST [0x{addr:X}]")?,
+            Instruction::LD { addr } => writeln!(buf, "LD [0x{addr:X}]")?,
+            Instruction::ROL { imm } => writeln!(buf, "ROL #{imm}")?,
+            Instruction::HALT => writeln!(buf, "HALT")?,
+        };
     }
 
     Ok(buf)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let input = std::env::args()
-        .nth(1)
-        .ok_or_else(|| "Missing .t8b binary file".to_string())?;
+    let mut input = None;
+    let mut config = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                config = Some(
+                    args.next()
+                        .ok_or_else(|| "--config requires a path to a t8.toml".to_string())?,
+                )
+            }
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or_else(|| "Missing .t8b binary file".to_string())?;
+
+    let devices = match config {
+        Some(path) => toml::from_slice::<config::Config>(&fs::read(&path)?)
+            .map_err(|e| format!("Failed to parse {path}: {e}"))?
+            .devices(),
+        None => vec![],
+    };
+
+    let debug = DebugInfo::load_for(Path::new(&input))?;
     let mut handle = std::io::stdout().lock();
-    handle.write_all(&dis(&shared::scriptorium::from(&fs::read(&input)?)?)?)?;
+    let reader = ScriptReader::new(std::io::BufReader::new(fs::File::open(&input)?))?;
+    handle.write_all(&dis(reader, debug.as_ref(), &devices)?)?;
     Ok(handle.flush()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_operands() {
+        let mut binary = shared::scriptorium::MAGIC.to_vec();
+        binary.extend([0x1F, 0x5F, 0x63]);
+        let out = dis(
+            ScriptReader::new(binary.as_slice()).unwrap(),
+            None,
+            &[("led".into(), 0xF)],
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let code = out
+            .lines()
+            .filter(|l| !l.starts_with(';') && !l.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            code,
+            [".const led 0xF", "LOADI #15", "ST [led]", "LD [0x3]"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let mut binary = shared::scriptorium::MAGIC.to_vec();
+        binary.extend([0x9A, 0x25, 0x80]);
+        let out = String::from_utf8(
+            dis(ScriptReader::new(binary.as_slice()).unwrap(), None, &[]).unwrap(),
+        )
+        .unwrap();
+        let code = out
+            .lines()
+            .filter(|l| !l.starts_with(';') && !l.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(code, [".byte 0x9A ; invalid", ".byte 0x25 ; MOV", "HALT"]);
+    }
+
+    /// xorshift, good enough for generating programs and keeps the test deterministic
+    fn next(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    #[test]
+    fn test_roundtrip_random_binaries() {
+        let mut state = 0x7a6e_6f38;
+        let devices = [("stdout".to_string(), 0x0), ("led".to_string(), 0xF)];
+        for _ in 0..512 {
+            let len = next(&mut state) % 64;
+            let mut binary = shared::scriptorium::MAGIC.to_vec();
+            for _ in 0..len {
+                binary.push(next(&mut state) as u8);
+            }
+
+            for devices in [&devices[..], &[]] {
+                let src =
+                    dis(ScriptReader::new(binary.as_slice()).unwrap(), None, devices).unwrap();
+                let reassembled = r#as::assemble(&src, "roundtrip.t8")
+                    .unwrap_or_else(|e| panic!("{e:?} in:\n{}", String::from_utf8_lossy(&src)));
+                let mut buf = Vec::new();
+                shared::scriptorium::Script::new(&mut buf)
+                    .unwrap()
+                    .add_words(&reassembled.words)
+                    .unwrap();
+                assert_eq!(binary, buf, "{}", String::from_utf8_lossy(&src));
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use shared::debug::DebugInfo;
+
+/// Executions per pc, written as an lcov tracefile mapped back to source lines.
+#[derive(Debug)]
+pub struct Coverage {
+    hits: Vec<usize>,
+}
+
+impl Coverage {
+    pub fn new(program_len: usize) -> Self {
+        Self {
+            hits: vec![0; program_len],
+        }
+    }
+
+    pub fn record(&mut self, pc: u8) {
+        if let Some(hits) = self.hits.get_mut(pc as usize) {
+            *hits += 1;
+        }
+    }
+
+    /// Hits per one based source line of every instruction `info` has a location for. A line
+    /// expanding to several instructions, like `.load`, counts as often as its most executed
+    /// instruction.
+    fn lines(&self, info: &DebugInfo) -> BTreeMap<usize, usize> {
+        let mut lines = BTreeMap::new();
+        for loc in &info.locs {
+            let hits = self.hits.get(loc.pc).copied().unwrap_or(0);
+            let line = lines.entry(loc.line + 1).or_insert(0);
+            *line = hits.max(*line);
+        }
+        lines
+    }
+
+    /// the lcov tracefile for the source `info` refers to, see `geninfo(1)`
+    pub fn lcov(&self, info: &DebugInfo) -> String {
+        let lines = self.lines(info);
+        let mut out = format!("TN:\nSF:{}\n", info.file);
+        for (line, hits) in &lines {
+            out.push_str(&format!("DA:{line},{hits}\n"));
+        }
+        out.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            lines.len(),
+            lines.values().filter(|hits| **hits > 0).count()
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::debug::Loc;
+
+    #[test]
+    fn test_lcov() {
+        let mut info = DebugInfo::new("examples/led.t8");
+        for (pc, line) in [(0, 3), (1, 3), (2, 4), (3, 6)] {
+            info.locs.push(Loc {
+                pc,
+                line,
+                col: 4,
+                operand: None,
+            });
+        }
+        let mut coverage = Coverage::new(4);
+        for pc in [0, 1, 2] {
+            coverage.record(pc);
+        }
+        coverage.record(1);
+
+        assert_eq!(
+            coverage.lcov(&info),
+            "TN:\nSF:examples/led.t8\nDA:4,2\nDA:5,1\nDA:7,0\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+}
@@ -1,71 +1,318 @@
-use std::{
-    collections::HashMap,
-    fs::{File, OpenOptions},
-    io::Write,
-};
+use std::collections::HashMap;
+
+use shared::{asm::Instruction, config::Config, debug::DebugInfo, scriptorium::InvalidByte};
 
-use shared::asm::Instruction;
+use crate::{
+    device::{Input, Mapped},
+    snapshot::Snapshot,
+};
 
-use crate::config::Config;
+/// registers and the memory cell a single step overwrote, enough to undo it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Undo {
+    pc: u8,
+    ac: u8,
+    dest: u8,
+    halted: bool,
+    /// address and previous value of the cell written by ST
+    mem: Option<(u8, u8)>,
+    /// address and previous position of the device accessed by ST or LD, see
+    /// [`Mapped::position`]
+    dev: Option<(u8, u64)>,
+}
 
 #[derive(Debug)]
 pub struct Cpu<'cpu> {
-    ins: &'cpu [Instruction],
+    ins: &'cpu [Result<Instruction, InvalidByte>],
     ac: u8,
     dest: u8,
     pc: u8,
-    mem: [u8; 16],
+    mem: Vec<u8>,
     pub halted: bool,
+    /// steps taken, the clock of the timer device
+    cycles: usize,
     config: &'cpu Config,
-    dev: HashMap<u8, File>,
+    /// print a trace line per step, defaults to `verbose` in t8.toml
+    verbose: bool,
+    dev: HashMap<u8, Mapped>,
+    /// debug info and the lines of the source it refers to
+    debug: Option<(&'cpu DebugInfo, &'cpu [String])>,
+    /// one entry per step taken, `None` unless enabled via [`Cpu::with_undo`]
+    undo: Option<Vec<Undo>>,
+    /// why the last step faulted, if a device failed, see [`Cpu::device_error`]
+    device_error: Option<String>,
 }
 
 impl<'cpu> Cpu<'cpu> {
-    pub fn new(config: &'cpu Config, ins: &'cpu [Instruction]) -> Self {
-        Self {
+    /// `mem` holds the memory before the first step, usually [`Memory::initial`]. Fails if a
+    /// device can not be opened, see [`Mapped::open`].
+    ///
+    /// [`Memory::initial`]: shared::config::Memory::initial
+    pub fn new(
+        config: &'cpu Config,
+        ins: &'cpu [Result<Instruction, InvalidByte>],
+        mem: Vec<u8>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let dev = config
+            .io
+            .iter()
+            .map(|(name, dev)| Ok((dev.addr, Mapped::open(name, dev)?)))
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+        Ok(Self {
             ins,
             ac: 0,
             dest: 0,
             pc: 0,
-            mem: [0; 16],
+            mem,
             halted: false,
+            cycles: 0,
             config,
-            dev: config
+            verbose: config.verbose,
+            dev,
+            debug: None,
+            undo: None,
+            device_error: None,
+        })
+    }
+
+    /// record every step, so it can be undone via [`Cpu::step_back`]. Without branches the log
+    /// never grows larger than the program.
+    pub fn with_undo(mut self) -> Self {
+        self.undo = Some(vec![]);
+        self
+    }
+
+    /// no trace and no device output on stdout, for front ends owning the terminal
+    pub fn quiet(mut self) -> Self {
+        self.verbose = false;
+        self.dev.values_mut().for_each(Mapped::mute);
+        self
+    }
+
+    /// consoles read from `input` instead of stdin, see [`Input`]
+    pub fn set_input(&mut self, input: Input) {
+        self.dev
+            .values_mut()
+            .for_each(|dev| dev.set_input(input.clone()));
+    }
+
+    /// attach debug info, used for showing source lines in traces and faults
+    pub fn with_debug(mut self, info: &'cpu DebugInfo, lines: &'cpu [String]) -> Self {
+        self.debug = Some((info, lines));
+        self
+    }
+
+    pub fn pc(&self) -> u8 {
+        self.pc
+    }
+
+    pub fn ac(&self) -> u8 {
+        self.ac
+    }
+
+    pub fn dest(&self) -> u8 {
+        self.dest
+    }
+
+    pub fn mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// steps taken, including the ones before a resumed snapshot, see [`Cpu::restore`]
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// registers and memory as modified by a debugger, bypassing devices
+    pub fn set_pc(&mut self, pc: u8) {
+        self.pc = pc;
+    }
+
+    pub fn set_ac(&mut self, ac: u8) {
+        self.ac = ac;
+    }
+
+    pub fn set_dest(&mut self, dest: u8) {
+        self.dest = dest;
+    }
+
+    pub fn mem_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    /// instruction at `pc`, `None` past the end of the program
+    pub fn instruction(&self, pc: u8) -> Option<&Result<Instruction, InvalidByte>> {
+        self.ins.get(pc as usize)
+    }
+
+    /// Undoes the last step, what it wrote to a device is cut from the device's file and read
+    /// devices are rewound. Returns false if there is nothing left to undo.
+    pub fn step_back(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(undo) = self.undo.as_mut().and_then(Vec::pop) else {
+            return Ok(false);
+        };
+        self.pc = undo.pc;
+        self.ac = undo.ac;
+        self.dest = undo.dest;
+        self.halted = undo.halted;
+        self.cycles -= 1;
+        if let Some((addr, old)) = undo.mem {
+            self.mem[addr as usize] = old;
+        }
+        if let Some((addr, pos)) = undo.dev
+            && let Some(dev) = self.dev.get_mut(&addr)
+        {
+            dev.rewind(pos)?;
+        }
+        Ok(true)
+    }
+
+    /// `<file>:<line>:<col>: <source line>` of the instruction at `pc`, if debug info is attached
+    pub fn location(&self, pc: u8) -> Option<String> {
+        let (info, lines) = self.debug?;
+        let loc = info.loc(pc as usize)?;
+        Some(format!(
+            "{}:{}:{}: {}",
+            info.file,
+            loc.line + 1,
+            loc.col + 1,
+            lines.get(loc.line).map(|l| l.trim()).unwrap_or_default()
+        ))
+    }
+
+    /// Full machine state, `binary`, `program` and `steps` are recorded as given. Devices are
+    /// recorded by their position, see [`Mapped::position`].
+    pub fn snapshot(
+        &self,
+        binary: &str,
+        program: &[u8],
+        steps: usize,
+    ) -> Result<Snapshot, Box<dyn std::error::Error>> {
+        let mut devices = self
+            .config
+            .io
+            .iter()
+            .filter_map(|(name, dev)| Some((name, self.dev.get(&dev.addr)?)))
+            .filter_map(|(name, dev)| {
+                dev.position()
+                    .transpose()
+                    .map(|pos| Ok((name.clone(), pos?)))
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        devices.sort();
+        Ok(Snapshot {
+            binary: binary.to_string(),
+            program: program.to_vec(),
+            steps,
+            pc: self.pc,
+            ac: self.ac,
+            dest: self.dest,
+            halted: self.halted,
+            mem: self.mem.clone(),
+            devices,
+        })
+    }
+
+    /// Restores the state recorded in `snap`, devices are rewound to the position they had when
+    /// the snapshot was taken, so resuming writes and reads the same bytes again.
+    pub fn restore(&mut self, snap: &Snapshot) -> Result<(), Box<dyn std::error::Error>> {
+        self.pc = snap.pc;
+        self.ac = snap.ac;
+        self.dest = snap.dest;
+        self.halted = snap.halted;
+        if snap.mem.len() != self.mem.len() {
+            return Err(format!(
+                "Snapshot has {} bytes of memory, t8.toml configures {}",
+                snap.mem.len(),
+                self.mem.len()
+            )
+            .into());
+        }
+        self.mem.copy_from_slice(&snap.mem);
+        self.cycles = snap.steps;
+        for (name, pos) in &snap.devices {
+            let Some(dev) = self
+                .config
                 .io
-                .values()
-                .map(|dev| {
-                    (
-                        dev.addr,
-                        OpenOptions::new()
-                            .create(true)
-                            .append(true)
-                            .open(&dev.file)
-                            .expect("Failed to open mapped dev file"),
-                    )
-                })
-                .collect(),
+                .get(name)
+                .and_then(|dev| self.dev.get_mut(&dev.addr))
+            else {
+                return Err(format!("Snapshot device `{name}` is not mapped in t8.toml").into());
+            };
+            dev.rewind(*pos)?;
         }
+        Ok(())
+    }
+
+    /// the i/o error of the device that made the last [`Cpu::step`] fault, if any
+    pub fn device_error(&self) -> Option<&str> {
+        self.device_error.as_deref()
+    }
+
+    /// records `err` of the device at `addr` as the reason for the fault the caller reports
+    fn device_failed(&mut self, addr: u8, err: std::io::Error) {
+        self.device_error = Some(format!("device at 0x{addr:X} failed: {err}"));
     }
 
     /// dump val into mem at addr
     fn dump(&mut self, addr: u8, val: u8) -> Option<()> {
-        *self.mem.get_mut(addr as usize)? = val;
-        if let Some(mut dev) = self.dev.get(&addr) {
-            dev.write_all(&[val])
-                .expect("Failed to write byte into memory mapped device");
+        self.mem.get(addr as usize)?;
+        if let Some(dev) = self.dev.get_mut(&addr)
+            && let Err(e) = dev.write(val)
+        {
+            self.device_failed(addr, e);
+            return None;
         }
+        self.mem[addr as usize] = val;
         Some(())
     }
 
     pub fn step(&mut self) -> Option<()> {
+        self.device_error = None;
+        let mut undo = Undo {
+            pc: self.pc,
+            ac: self.ac,
+            dest: self.dest,
+            halted: self.halted,
+            mem: None,
+            dev: None,
+        };
+
         if self.pc as usize >= self.ins.len() {
             self.halted = true;
+            self.cycles += 1;
+            if let Some(log) = &mut self.undo {
+                log.push(undo);
+            }
             return Some(());
         }
 
-        let cur = &self.ins[self.pc as usize];
+        // invalid bytes only fault once they are executed
+        let Ok(cur) = &self.ins[self.pc as usize] else {
+            return None;
+        };
+        // reserved and missing memory faults
+        if let Instruction::ST { addr } | Instruction::LD { addr } = cur
+            && !self.config.memory.accessible(*addr)
+        {
+            return None;
+        }
+        if let Instruction::ST { addr } = cur {
+            undo.mem = Some((*addr, *self.mem.get(*addr as usize)?));
+        }
+        if let Instruction::ST { addr } | Instruction::LD { addr } = cur
+            && let (Some(_), Some(dev)) = (&self.undo, self.dev.get(addr))
+        {
+            match dev.position() {
+                Ok(pos) => undo.dev = pos.map(|pos| (*addr, pos)),
+                Err(e) => {
+                    self.device_failed(*addr, e);
+                    return None;
+                }
+            }
+        }
 
-        if self.config.verbose {
+        if self.verbose {
             println!(
                 "{:04x}: 0x{:X} {:>8} (op=0x{:X}, imm=0x{:X}) [ac=0x{:X},dest=0x{:X}]",
                 self.pc,
@@ -76,6 +323,9 @@ impl<'cpu> Cpu<'cpu> {
                 self.ac,
                 self.dest,
             );
+            if let Some(loc) = self.location(self.pc) {
+                println!("      ; {loc}");
+            }
         }
 
         match cur {
@@ -86,11 +336,40 @@ impl<'cpu> Cpu<'cpu> {
             Instruction::ADD => self.ac = self.dest.wrapping_add(self.ac),
             Instruction::SUB => self.ac = self.dest.wrapping_sub(self.ac),
             Instruction::ST { addr } => self.dump(*addr, self.ac)?,
-            Instruction::LD { addr } => self.ac = *self.mem.get(*addr as usize)?,
+            Instruction::LD { addr } => {
+                let read = match self.dev.get_mut(addr).map(|dev| dev.read(self.cycles)) {
+                    Some(Ok(read)) => read,
+                    Some(Err(e)) => {
+                        self.device_failed(*addr, e);
+                        return None;
+                    }
+                    None => None,
+                };
+                self.ac = match read {
+                    Some(value) => value,
+                    None => *self.mem.get(*addr as usize)?,
+                }
+            }
             Instruction::ROL { imm } => self.ac = self.ac.rotate_left((*imm & 0xF) as u32),
         }
         self.pc += 1;
+        self.cycles += 1;
+        if let Some(log) = &mut self.undo {
+            log.push(undo);
+        }
 
         Some(())
     }
 }
+
+#[cfg(test)]
+impl<'cpu> Cpu<'cpu> {
+    /// a muted cpu on the initial memory of `config`, recording undo information
+    pub fn for_test(config: &'cpu Config, ins: &'cpu [Result<Instruction, InvalidByte>]) -> Self {
+        let mem = config.memory.initial().expect("invalid test memory");
+        Self::new(config, ins, mem)
+            .expect("invalid test devices")
+            .quiet()
+            .with_undo()
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use shared::asm::Instruction;
+
+use crate::{cpu::Cpu, device::Input};
+
+const HELP: &str = "\
+s, step [n]              execute the next n instructions, defaults to 1
+c, continue              run until a breakpoint or HALT
+b, break <pc>            toggle a breakpoint before the instruction at pc
+bs, back [n]             undo the last n steps, defaults to 1
+rc, reverse-continue     undo steps until a breakpoint or the start of the program
+r, regs                  print pc, ac and dest
+m, mem                   print memory
+q, quit                  stop debugging";
+
+/// `LOADI #1`, `ST [0xF]` and friends, the syntax `dis` emits
+pub(crate) fn describe(ins: &Instruction) -> String {
+    match ins {
+        Instruction::LOADI { imm } | Instruction::ROL { imm } => {
+            format!("{} #{imm}", ins.to_str_lossy())
+        }
+        Instruction::ST { addr } | Instruction::LD { addr } => {
+            format!("{} [0x{addr:X}]", ins.to_str_lossy())
+        }
+        _ => ins.to_str_lossy().to_string(),
+    }
+}
+
+/// accepts `0x` prefixed hex and decimal, like the assembler
+fn parse_num(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+struct Debugger<'d, 'cpu, W: Write> {
+    cpu: &'d mut Cpu<'cpu>,
+    out: W,
+    breakpoints: BTreeSet<u8>,
+}
+
+impl<W: Write> Debugger<'_, '_, W> {
+    /// prints the instruction about to be executed
+    fn current(&mut self) -> std::io::Result<()> {
+        let pc = self.cpu.pc();
+        if self.cpu.halted {
+            return writeln!(self.out, "{pc:04x}: halted");
+        }
+        let ins = match self.cpu.instruction(pc) {
+            Some(Ok(ins)) => describe(ins),
+            Some(Err(invalid)) => invalid.to_string(),
+            None => "end of program".into(),
+        };
+        match self.cpu.location(pc) {
+            Some(loc) => writeln!(self.out, "{pc:04x}: {ins:<12} ; {loc}"),
+            None => writeln!(self.out, "{pc:04x}: {ins}"),
+        }
+    }
+
+    /// single step, false once halted or faulted
+    fn step(&mut self) -> std::io::Result<bool> {
+        if self.cpu.halted {
+            return Ok(false);
+        }
+        if self.cpu.step().is_none() {
+            match self.cpu.device_error() {
+                Some(e) => writeln!(self.out, "Fault at {:04x}, {e}", self.cpu.pc())?,
+                None => writeln!(self.out, "Fault at {:04x}", self.cpu.pc())?,
+            }
+            return Ok(false);
+        }
+        Ok(!self.cpu.halted)
+    }
+
+    /// runs a single command, returns false on quit
+    fn command(&mut self, line: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut parts = line.split_whitespace();
+        let count = |arg: Option<&str>| arg.and_then(parse_num).unwrap_or(1);
+        match parts.next() {
+            None => return Ok(true),
+            Some("s" | "step") => {
+                for _ in 0..count(parts.next()) {
+                    if !self.step()? {
+                        break;
+                    }
+                }
+            }
+            Some("c" | "continue") => {
+                while self.step()? && !self.breakpoints.contains(&self.cpu.pc()) {}
+            }
+            Some("b" | "break") => {
+                let Some(pc) = parts.next().and_then(parse_num) else {
+                    writeln!(self.out, "break requires a pc")?;
+                    return Ok(true);
+                };
+                let Ok(pc) = u8::try_from(pc) else {
+                    writeln!(self.out, "pc out of range")?;
+                    return Ok(true);
+                };
+                if self.breakpoints.remove(&pc) {
+                    writeln!(self.out, "removed breakpoint at {pc:04x}")?;
+                } else {
+                    self.breakpoints.insert(pc);
+                    writeln!(self.out, "breakpoint at {pc:04x}")?;
+                }
+                return Ok(true);
+            }
+            Some("bs" | "back") => {
+                for _ in 0..count(parts.next()) {
+                    if !self.cpu.step_back()? {
+                        writeln!(self.out, "at the start of the program")?;
+                        break;
+                    }
+                }
+            }
+            Some("rc" | "reverse-continue") => {
+                while self.cpu.step_back()? && !self.breakpoints.contains(&self.cpu.pc()) {}
+            }
+            Some("r" | "regs") => {
+                writeln!(
+                    self.out,
+                    "pc=0x{:X} ac=0x{:X} dest=0x{:X}",
+                    self.cpu.pc(),
+                    self.cpu.ac(),
+                    self.cpu.dest()
+                )?;
+                return Ok(true);
+            }
+            Some("m" | "mem") => {
+                let mem = self
+                    .cpu
+                    .mem()
+                    .iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<Vec<_>>();
+                writeln!(self.out, "{}", mem.join(" "))?;
+                return Ok(true);
+            }
+            Some("q" | "quit") => return Ok(false),
+            Some(_) => {
+                writeln!(self.out, "{HELP}")?;
+                return Ok(true);
+            }
+        }
+        self.current()?;
+        Ok(true)
+    }
+}
+
+/// Interactive debugger reading commands from `input`, see [`HELP`]. Consoles read from `input`
+/// as well, between commands. The cpu has to record undo information, see [`Cpu::with_undo`].
+pub fn run<R: BufRead + 'static, W: Write>(
+    cpu: &mut Cpu,
+    input: R,
+    out: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let input = Input(Rc::new(RefCell::new(input)));
+    cpu.set_input(input.clone());
+    let mut debugger = Debugger {
+        cpu,
+        out,
+        breakpoints: BTreeSet::new(),
+    };
+    debugger.current()?;
+    loop {
+        let mut line = String::new();
+        if input.0.borrow_mut().read_line(&mut line)? == 0 || !debugger.command(&line)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::asm::Instruction::*;
+    use shared::config::{Config, Device, Kind};
+
+    #[test]
+    fn test_reverse_continue_to_breakpoint() {
+        let config = Config::default();
+        let ins = [
+            LOADI { imm: 1 },
+            MOV,
+            ADD,
+            ST { addr: 3 },
+            LOADI { imm: 7 },
+            HALT,
+        ]
+        .map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+
+        let mut out = Vec::new();
+        run(
+            &mut cpu,
+            "b 300\nb 2\nc\nc\nm\nrc\nr\nbs 5\nm\nq\n".as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+0000: LOADI #1
+pc out of range
+breakpoint at 0002
+0002: ADD
+0006: halted
+00 00 00 02 00 00 00 00 00 00 00 00 00 00 00 00
+0002: ADD
+pc=0x2 ac=0x1 dest=0x1
+at the start of the program
+0000: LOADI #1
+00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+"
+        );
+    }
+
+    #[test]
+    fn test_console_reads_between_commands() {
+        let config = Config::with_device(
+            "console",
+            Device {
+                addr: 0,
+                kind: Kind::Console,
+                ..Default::default()
+            },
+        );
+        let ins = [LD { addr: 0 }, HALT].map(Ok);
+        let mut cpu = Cpu::new(&config, &ins, vec![0; 16]).unwrap().with_undo();
+
+        let mut out = Vec::new();
+        run(&mut cpu, "c\nAr\nq\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0000: LD [0x0]\n0002: halted\npc=0x2 ac=0x41 dest=0x0\n"
+        );
+    }
+}
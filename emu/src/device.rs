@@ -0,0 +1,442 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufRead, ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    rc::Rc,
+};
+
+use shared::config::{Device, Kind};
+
+/// segment masks for the hex digits 0-F, bit 0 is segment a, bit 6 is segment g
+const DIGITS: [u8; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07, 0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+/// Next state of the 8 bit galois LFSR with the taps 8, 6, 5 and 4, it cycles through all 255
+/// non zero values.
+pub fn lfsr(state: u8) -> u8 {
+    (state >> 1) ^ if state & 1 != 0 { 0xB8 } else { 0 }
+}
+
+/// `●` for every set bit and `○` for every cleared one, most significant bit first
+pub fn led8(value: u8) -> String {
+    (0..8)
+        .rev()
+        .map(|bit| {
+            if value & (1 << bit) != 0 {
+                '●'
+            } else {
+                '○'
+            }
+        })
+        .collect()
+}
+
+/// Segment mask of the low nibble of `value` as a hex digit.
+pub fn digit(value: u8) -> u8 {
+    DIGITS[(value & 0xF) as usize]
+}
+
+/// Three lines of ascii art for the segment `mask`, bits 0-6 are the segments a-g, bit 7 is the
+/// decimal point:
+///
+/// ```text
+///  _
+/// |_|
+/// |_|.
+/// ```
+pub fn sevenseg(mask: u8) -> [String; 3] {
+    let seg = |bit: u8, c: char| if mask & (1 << bit) != 0 { c } else { ' ' };
+    [
+        format!(" {} ", seg(0, '_')),
+        format!("{}{}{}", seg(5, '|'), seg(6, '_'), seg(1, '|')),
+        format!(
+            "{}{}{}{}",
+            seg(4, '|'),
+            seg(3, '_'),
+            seg(2, '|'),
+            seg(7, '.')
+        ),
+    ]
+}
+
+/// Input a `console` reads from instead of stdin, shared with another reader of the same stream
+/// like the debugger, which reads its commands from stdin while holding its lock.
+#[derive(Clone)]
+pub struct Input(pub Rc<RefCell<dyn BufRead>>);
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Input")
+    }
+}
+
+/// A device as mapped into the memory of a [`crate::cpu::Cpu`].
+#[derive(Debug)]
+pub struct Mapped {
+    kind: Kind,
+    segments: bool,
+    hex: bool,
+    prescaler: usize,
+    /// state of an `rng`, never 0
+    lfsr: u8,
+    /// `None` renders to the terminal
+    file: Option<File>,
+    /// connection of a `socket` device, takes precedence over `file`
+    socket: Option<UnixStream>,
+    /// whether an `LD` of a `socket` waits for a byte, writes always block
+    blocking: bool,
+    /// set for devices rendering to the terminal while something else owns it, like the tui
+    muted: bool,
+    /// terminal output is line buffered, so it does not tear through the trace
+    line: Vec<u8>,
+    /// where a `console` reads from, `None` reads stdin
+    input: Option<Input>,
+}
+
+impl Mapped {
+    pub fn open(name: &str, dev: &Device) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match &dev.file {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("Failed to open `{path}` for device `{name}`: {e}"))?,
+            ),
+            None if dev.kind == Kind::File => {
+                return Err(format!("Device `{name}` requires a file").into());
+            }
+            None => None,
+        };
+        let socket = match &dev.socket {
+            Some(path) if dev.kind == Kind::Socket => {
+                let socket = UnixStream::connect(path).map_err(|e| {
+                    format!("Failed to connect to `{path}` for device `{name}`: {e}")
+                })?;
+                Some(socket)
+            }
+            None if dev.kind == Kind::Socket => {
+                return Err(format!("Device `{name}` requires a socket").into());
+            }
+            _ => None,
+        };
+        let prescaler = dev.prescaler.unwrap_or(1);
+        if prescaler == 0 {
+            return Err(format!("Prescaler of device `{name}` has to be at least 1").into());
+        }
+        let lfsr = dev.seed.unwrap_or(1);
+        if lfsr == 0 {
+            return Err(format!("Seed of device `{name}` must not be 0").into());
+        }
+        Ok(Self {
+            kind: dev.kind,
+            segments: dev.segments,
+            hex: dev.hex,
+            prescaler,
+            lfsr,
+            file,
+            socket,
+            blocking: dev.blocking.unwrap_or(true),
+            muted: false,
+            line: vec![],
+            input: None,
+        })
+    }
+
+    pub fn mute(&mut self) {
+        self.muted = true;
+    }
+
+    pub fn set_input(&mut self, input: Input) {
+        self.input = Some(input);
+    }
+
+    /// the text written for `value`, a full frame for the rendering kinds
+    fn render(&self, value: u8) -> Vec<u8> {
+        match self.kind {
+            Kind::File | Kind::Socket => vec![value],
+            Kind::Console if self.hex => format!("{value:02X}\n").into_bytes(),
+            Kind::Console => vec![value],
+            Kind::Timer | Kind::Rng => vec![],
+            Kind::Led8 => format!("{}\n", led8(value)).into_bytes(),
+            Kind::Sevenseg => {
+                let mask = if self.segments { value } else { digit(value) };
+                let mut frame = sevenseg(mask).join("\n");
+                frame.push('\n');
+                frame.into_bytes()
+            }
+        }
+    }
+
+    pub fn write(&mut self, value: u8) -> std::io::Result<()> {
+        let bytes = self.render(value);
+        if let Some(socket) = &mut self.socket {
+            return socket.write_all(&bytes);
+        }
+        match &mut self.file {
+            Some(file) => file.write_all(&bytes),
+            None if self.muted => Ok(()),
+            None => {
+                for b in bytes {
+                    self.line.push(b);
+                    if b == b'\n' {
+                        let mut stdout = std::io::stdout().lock();
+                        stdout.write_all(&self.line)?;
+                        stdout.flush()?;
+                        self.line.clear();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Serves an `LD` from the device after `cycles` executed instructions, `None` if the device
+    /// can not be read and `LD` returns the memory cell instead. The console reads a byte from
+    /// stdin or its [`Input`], 0 once that is exhausted, a socket reads the next byte from its peer, 0 once it
+    /// disconnected or, if non-blocking, if there is none yet.
+    pub fn read(&mut self, cycles: usize) -> std::io::Result<Option<u8>> {
+        match self.kind {
+            Kind::Timer => Ok(Some((cycles / self.prescaler) as u8)),
+            Kind::Socket => {
+                let Some(socket) = &mut self.socket else {
+                    return Ok(Some(0));
+                };
+                let mut byte = [0];
+                // only reads may skip waiting, a write has to reach the peer
+                socket.set_nonblocking(!self.blocking)?;
+                let read = socket.read(&mut byte);
+                socket.set_nonblocking(false)?;
+                match read {
+                    Ok(0) => Ok(Some(0)),
+                    Ok(_) => Ok(Some(byte[0])),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(Some(0)),
+                    Err(e) => Err(e),
+                }
+            }
+            Kind::Rng => {
+                self.lfsr = lfsr(self.lfsr);
+                Ok(Some(self.lfsr))
+            }
+            Kind::Console if self.muted => Ok(Some(0)),
+            Kind::Console => {
+                // a prompt without a newline has to show before waiting for the answer
+                if !self.line.is_empty() {
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(&self.line)?;
+                    stdout.flush()?;
+                    self.line.clear();
+                }
+                let mut byte = [0];
+                let read = match &self.input {
+                    Some(input) => input.0.borrow_mut().read(&mut byte)?,
+                    None => std::io::stdin().lock().read(&mut byte)?,
+                };
+                match read {
+                    0 => Ok(Some(0)),
+                    _ => Ok(Some(byte[0])),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// State needed to rewind the device: the bytes written to its file, or the state of an
+    /// `rng`. `None` for devices rendering to the terminal, which can not be rewound.
+    pub fn position(&self) -> std::io::Result<Option<u64>> {
+        if self.kind == Kind::Rng {
+            return Ok(Some(self.lfsr as u64));
+        }
+        self.file
+            .as_ref()
+            .map(|f| Ok(f.metadata()?.len()))
+            .transpose()
+    }
+
+    /// Rewinds the device to `pos`, see [`Mapped::position`]. Files are cut back to `pos`
+    /// bytes, if they are longer.
+    pub fn rewind(&mut self, pos: u64) -> std::io::Result<()> {
+        if self.kind == Kind::Rng {
+            self.lfsr = pos as u8;
+            return Ok(());
+        }
+        match &self.file {
+            Some(file) if file.metadata()?.len() > pos => file.set_len(pos),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Mapped {
+    /// ends an unterminated line on the terminal, a program printing `HELLO` has no newline
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            let _ = std::io::stdout().write_all(&self.line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu;
+    use shared::{asm::Instruction::*, config::Config};
+
+    #[test]
+    fn test_render() {
+        assert_eq!(led8(0xD), "○○○○●●○●");
+        assert_eq!(sevenseg(digit(8)), [" _ ", "|_|", "|_| "]);
+        assert_eq!(sevenseg(digit(1)), ["   ", "  |", "  | "]);
+        assert_eq!(sevenseg(digit(0xF) | 0x80), [" _ ", "|_ ", "|  ."]);
+
+        let console = Device {
+            kind: Kind::Console,
+            hex: true,
+            ..Default::default()
+        };
+        let mut console = Mapped::open("stdout", &console).unwrap();
+        console.mute();
+        assert_eq!(console.render(b'H'), b"48\n");
+        assert_eq!(console.read(0).unwrap(), Some(0));
+        assert!(Mapped::open("led", &Device::default()).is_err());
+    }
+
+    #[test]
+    fn test_console_shows_prompt_before_reading() {
+        let console = Device {
+            kind: Kind::Console,
+            ..Default::default()
+        };
+        let mut mapped = Mapped::open("console", &console).unwrap();
+        mapped.set_input(Input(Rc::new(RefCell::new("y".as_bytes()))));
+        mapped.write(b'?').unwrap();
+        assert_eq!(mapped.line, b"?");
+        assert_eq!(mapped.read(0).unwrap(), Some(b'y'));
+        assert!(mapped.line.is_empty());
+    }
+
+    #[test]
+    fn test_rng_is_deterministic() {
+        let mut state = 1;
+        let period = (1..=256).find(|_| {
+            state = lfsr(state);
+            state == 1
+        });
+        assert_eq!(period, Some(255));
+
+        let config = Config::with_device(
+            "rng",
+            Device {
+                addr: 0xD,
+                kind: Kind::Rng,
+                seed: Some(0x2A),
+                ..Default::default()
+            },
+        );
+        let ins = [LD { addr: 0xD }, MOV, LD { addr: 0xD }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!((cpu.dest(), cpu.ac()), (lfsr(0x2A), lfsr(lfsr(0x2A))));
+
+        cpu.step_back().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.ac(), lfsr(lfsr(0x2A)));
+    }
+
+    #[test]
+    fn test_socket_bridge() {
+        let path = std::env::temp_dir().join(format!("t8_bridge_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        // the peripheral increments every byte it receives
+        let peer = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            stream.write_all(&[byte[0] + 1]).unwrap();
+        });
+
+        let config = Config::with_device(
+            "bridge",
+            Device {
+                addr: 0xC,
+                kind: Kind::Socket,
+                socket: Some(path.to_string_lossy().into()),
+                ..Default::default()
+            },
+        );
+        let ins = [LOADI { imm: 5 }, ST { addr: 0xC }, LD { addr: 0xC }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.ac(), 6);
+        peer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_socket_peer_gone_faults() {
+        let path = std::env::temp_dir().join(format!("t8_gone_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let peer = std::thread::spawn(move || drop(listener.accept().unwrap()));
+
+        let config = Config::with_device(
+            "bridge",
+            Device {
+                addr: 0xC,
+                kind: Kind::Socket,
+                socket: Some(path.to_string_lossy().into()),
+                blocking: Some(false),
+                ..Default::default()
+            },
+        );
+        let ins = [LD { addr: 0xC }, ST { addr: 0xC }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        peer.join().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.ac(), 0);
+        assert!(cpu.step().is_none());
+        assert!(
+            cpu.device_error()
+                .is_some_and(|e| e.starts_with("device at 0xC failed")),
+            "{:?}",
+            cpu.device_error()
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_timer_ticks_with_prescaler() {
+        let config = Config::with_device(
+            "timer",
+            Device {
+                addr: 0xE,
+                kind: Kind::Timer,
+                prescaler: Some(2),
+                ..Default::default()
+            },
+        );
+        let ins = [NOP, NOP, NOP, LD { addr: 0xE }, MOV, LD { addr: 0xE }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!((cpu.ac(), cpu.dest()), (1, 1));
+        cpu.step().unwrap();
+        assert_eq!(cpu.ac(), 2);
+
+        cpu.step_back().unwrap();
+        cpu.step_back().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.ac(), 2);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
+};
+
+use crate::cpu::Cpu;
+
+/// register layout reported to gdb, every register is a single byte in this order
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.tango8.core">
+    <reg name="pc" bitsize="8" type="code_ptr" regnum="0"/>
+    <reg name="ac" bitsize="8" type="uint8"/>
+    <reg name="dest" bitsize="8" type="uint8"/>
+    <reg name="ir" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// SIGTRAP, reported whenever execution stops at a breakpoint or after a step
+const TRAP: &str = "S05";
+/// SIGSEGV, reported if the cpu faults on an invalid instruction
+const FAULT: &str = "S0b";
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `addr,len` as used by `m`, `M`, `Z` and `qXfer`
+fn addr_len(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((
+        usize::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Reads the payload of the next `$<payload>#<checksum>` packet, acking it. A `\x03` interrupt
+/// outside a packet is returned as is. `None` once the connection is closed.
+fn read_packet<R: Read, W: Write>(
+    r: &mut R,
+    w: &mut W,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut byte = [0];
+    loop {
+        if r.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            b'$' => break,
+            0x03 => return Ok(Some("\x03".into())),
+            // acks of our replies, and noise between packets
+            _ => {}
+        }
+    }
+
+    let mut payload = vec![];
+    loop {
+        r.read_exact(&mut byte)?;
+        if byte[0] == b'#' {
+            break;
+        }
+        payload.push(byte[0]);
+    }
+    let mut sum = [0; 2];
+    r.read_exact(&mut sum)?;
+    let valid = str::from_utf8(&sum)
+        .ok()
+        .and_then(|s| u8::from_str_radix(s, 16).ok())
+        == Some(checksum(&payload));
+    w.write_all(if valid { b"+" } else { b"-" })?;
+    w.flush()?;
+    if !valid {
+        return read_packet(r, w);
+    }
+    Ok(Some(String::from_utf8(payload)?))
+}
+
+fn write_packet<W: Write>(w: &mut W, payload: &str) -> std::io::Result<()> {
+    write!(w, "${payload}#{:02x}", checksum(payload.as_bytes()))?;
+    w.flush()
+}
+
+struct Stub<'s, 'cpu> {
+    cpu: &'s mut Cpu<'cpu>,
+    breakpoints: BTreeSet<u8>,
+}
+
+impl Stub<'_, '_> {
+    /// the instruction register, the encoded instruction at pc
+    fn ir(&self) -> u8 {
+        match self.cpu.instruction(self.cpu.pc()) {
+            Some(Ok(ins)) => ins.encode().unwrap_or(0),
+            Some(Err(invalid)) => invalid.byte,
+            None => 0,
+        }
+    }
+
+    fn regs(&self) -> [u8; 4] {
+        [self.cpu.pc(), self.cpu.ac(), self.cpu.dest(), self.ir()]
+    }
+
+    /// writes register `n`, the instruction register is read only
+    fn set_reg(&mut self, n: usize, value: u8) {
+        match n {
+            0 => self.cpu.set_pc(value),
+            1 => self.cpu.set_ac(value),
+            2 => self.cpu.set_dest(value),
+            _ => {}
+        }
+    }
+
+    /// single step forwards or backwards, `Some(stop reply)` if execution has to stop
+    fn step(&mut self, reverse: bool) -> Result<Option<&'static str>, Box<dyn std::error::Error>> {
+        if reverse {
+            return Ok((!self.cpu.step_back()?).then_some(TRAP));
+        }
+        if self.cpu.halted {
+            return Ok(Some("W00"));
+        }
+        if self.cpu.step().is_none() {
+            return Ok(Some(FAULT));
+        }
+        Ok(self.cpu.halted.then_some("W00"))
+    }
+
+    fn resume(
+        &mut self,
+        reverse: bool,
+        single: bool,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        loop {
+            if let Some(stop) = self.step(reverse)? {
+                return Ok(stop.into());
+            }
+            if single || self.breakpoints.contains(&self.cpu.pc()) {
+                return Ok(TRAP.into());
+            }
+        }
+    }
+
+    /// reply to `packet`, `None` if the session ends
+    fn handle(&mut self, packet: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let reply = match packet {
+            "?" | "\x03" => TRAP.into(),
+            "g" => hex(&self.regs()),
+            "s" => self.resume(false, true)?,
+            "c" => self.resume(false, false)?,
+            "bs" => self.resume(true, true)?,
+            "bc" => self.resume(true, false)?,
+            "qAttached" => "1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            "qC" => "QC1".into(),
+            // detach and kill both end the session
+            "D" | "k" => return Ok(None),
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;qXfer:features:read+;swbreak+;ReverseStep+;ReverseContinue+".into()
+            }
+            _ if packet.starts_with('H') => "OK".into(),
+            _ => {
+                if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+                    match addr_len(args) {
+                        Some((offset, len)) => {
+                            let chunk = TARGET_XML.get(offset..).unwrap_or("");
+                            if chunk.len() > len {
+                                format!("m{}", &chunk[..len])
+                            } else {
+                                format!("l{chunk}")
+                            }
+                        }
+                        None => "E01".into(),
+                    }
+                } else if let Some(values) = packet.strip_prefix('G') {
+                    match unhex(values) {
+                        Some(values) => {
+                            for (n, value) in values.into_iter().enumerate() {
+                                self.set_reg(n, value);
+                            }
+                            "OK".into()
+                        }
+                        None => "E01".into(),
+                    }
+                } else if let Some(n) = packet.strip_prefix('p') {
+                    match usize::from_str_radix(n, 16)
+                        .ok()
+                        .and_then(|n| self.regs().get(n).copied())
+                    {
+                        Some(value) => hex(&[value]),
+                        None => "E01".into(),
+                    }
+                } else if let Some(args) = packet.strip_prefix('P') {
+                    let parsed = args.split_once('=').and_then(|(n, value)| {
+                        Some((usize::from_str_radix(n, 16).ok()?, unhex(value)?))
+                    });
+                    match parsed {
+                        Some((n, value)) if n < 4 && value.len() == 1 => {
+                            self.set_reg(n, value[0]);
+                            "OK".into()
+                        }
+                        _ => "E01".into(),
+                    }
+                } else if let Some(args) = packet.strip_prefix('m') {
+                    match addr_len(args)
+                        .and_then(|(addr, len)| self.cpu.mem().get(addr..addr + len))
+                    {
+                        Some(bytes) => hex(bytes),
+                        None => "E01".into(),
+                    }
+                } else if let Some(args) = packet.strip_prefix('M') {
+                    let parsed = args.split_once(':').and_then(|(range, data)| {
+                        let (addr, len) = addr_len(range)?;
+                        let data = unhex(data)?;
+                        (data.len() == len).then_some((addr, data))
+                    });
+                    match parsed.and_then(|(addr, data)| {
+                        self.cpu
+                            .mem_mut()
+                            .get_mut(addr..addr + data.len())
+                            .map(|cells| cells.copy_from_slice(&data))
+                    }) {
+                        Some(()) => "OK".into(),
+                        None => "E01".into(),
+                    }
+                } else if let Some(args) = packet
+                    .strip_prefix("Z0,")
+                    .or_else(|| packet.strip_prefix("z0,"))
+                {
+                    match addr_len(args).and_then(|(addr, _)| u8::try_from(addr).ok()) {
+                        Some(pc) => {
+                            if packet.starts_with('Z') {
+                                self.breakpoints.insert(pc);
+                            } else {
+                                self.breakpoints.remove(&pc);
+                            }
+                            "OK".into()
+                        }
+                        None => "E01".into(),
+                    }
+                } else {
+                    // the empty reply marks a packet as unsupported
+                    String::new()
+                }
+            }
+        };
+        Ok(Some(reply))
+    }
+}
+
+/// Addresses to listen on for `--gdb <spec>`, a bare port listens on localhost. Addresses other
+/// hosts can reach are only accepted with `expose`, the stub gives full control over the cpu and
+/// its devices to whoever connects.
+pub fn listen_addrs(spec: &str, expose: bool) -> Result<Vec<SocketAddr>, String> {
+    if let Ok(port) = spec.parse::<u16>() {
+        return Ok(vec![(Ipv4Addr::LOCALHOST, port).into()]);
+    }
+    let addrs = spec
+        .to_socket_addrs()
+        .map_err(|e| format!("Invalid gdb address `{spec}`: {e}"))?
+        .collect::<Vec<_>>();
+    if !expose && addrs.iter().any(|a| !a.ip().is_loopback()) {
+        return Err(format!(
+            "`{spec}` is reachable from other hosts, pass --gdb-expose to listen on it anyway"
+        ));
+    }
+    Ok(addrs)
+}
+
+/// Serves a single gdb session over `r` and `w` until gdb detaches, kills the target or
+/// disconnects. Registers are pc, ac, dest and ir, the memory is mapped at 0.
+pub fn serve<R: Read, W: Write>(
+    cpu: &mut Cpu,
+    mut r: R,
+    mut w: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stub = Stub {
+        cpu,
+        breakpoints: BTreeSet::new(),
+    };
+    while let Some(packet) = read_packet(&mut r, &mut w)? {
+        match stub.handle(&packet)? {
+            Some(reply) => write_packet(&mut w, &reply)?,
+            None => {
+                write_packet(&mut w, "OK")?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::asm::Instruction::*;
+    use shared::config::Config;
+
+    #[test]
+    fn test_listen_addrs() {
+        assert_eq!(
+            listen_addrs("1234", false).unwrap(),
+            ["127.0.0.1:1234".parse().unwrap()]
+        );
+        assert!(listen_addrs("[::1]:1234", false).is_ok());
+        assert!(listen_addrs("0.0.0.0:1234", false).is_err());
+        assert!(listen_addrs("0.0.0.0:1234", true).is_ok());
+        assert!(listen_addrs("nonsense", false).is_err());
+    }
+
+    fn packet(payload: &str) -> String {
+        format!("${payload}#{:02x}", checksum(payload.as_bytes()))
+    }
+
+    #[test]
+    fn test_session() {
+        let config = Config::default();
+        let ins = [LOADI { imm: 1 }, MOV, ADD, ST { addr: 3 }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+
+        let requests = [
+            "?",
+            "Z0,3,1",
+            "c",
+            "g",
+            "bs",
+            "p2",
+            "M5,2:abcd",
+            "m2,4",
+            "c",
+            "D",
+        ];
+        let input = requests.iter().map(|p| packet(p)).collect::<String>();
+        let mut out = Vec::new();
+        serve(&mut cpu, input.as_bytes(), &mut out).unwrap();
+
+        let replies = [
+            "S05", "OK", "S05", "03020153", "S05", "01", "OK", "000000ab", "S05", "OK",
+        ];
+        let expected = replies
+            .iter()
+            .map(|r| format!("+{}", packet(r)))
+            .collect::<String>();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bad_checksum_is_nacked() {
+        let config = Config::default();
+        let ins = [HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        let mut out = Vec::new();
+        serve(&mut cpu, "$g#00".as_bytes(), &mut out).unwrap();
+        assert_eq!(out, b"-");
+    }
+}
@@ -1,24 +1,210 @@
-use std::fs;
+use std::{fs, path::Path};
 
-mod config;
+mod coverage;
 mod cpu;
+mod debugger;
+mod device;
+mod gdb;
+mod profile;
+mod snapshot;
+mod tui;
 
-use shared::scriptorium::from;
+use shared::{asm::Instruction, config, debug::DebugInfo, scriptorium::from};
+
+use crate::snapshot::Snapshot;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let input = std::env::args()
-        .nth(1)
-        .ok_or_else(|| "Missing .t8b binary file".to_string())?;
+    let mut input = None;
+    let mut snapshot_at: Option<(usize, String)> = None;
+    let mut resume = None;
+    let mut debug_mode = false;
+    let mut tui_mode = false;
+    let mut gdb = None;
+    let mut gdb_expose = false;
+    let mut config_path = "t8.toml".to_string();
+    let mut profile_format = None;
+    let mut profile_path = None;
+    let mut coverage_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--snapshot-at" => {
+                let usage = || "--snapshot-at requires a step count and a path".to_string();
+                let steps = args.next().ok_or_else(usage)?.parse()?;
+                snapshot_at = Some((steps, args.next().ok_or_else(usage)?));
+            }
+            "--debug" => debug_mode = true,
+            "--tui" => tui_mode = true,
+            "--gdb" => {
+                gdb = Some(args.next().ok_or_else(|| {
+                    "--gdb requires a port, a tcp address or unix:<path> to listen on".to_string()
+                })?)
+            }
+            "--gdb-expose" => gdb_expose = true,
+            "--config" => {
+                config_path = args
+                    .next()
+                    .ok_or_else(|| "--config requires a path to a t8.toml".to_string())?
+            }
+            "--coverage" => {
+                coverage_path = Some(args.next().ok_or_else(|| {
+                    "--coverage requires a path to write the lcov tracefile to".to_string()
+                })?)
+            }
+            "--resume" => {
+                resume = Some(
+                    args.next()
+                        .ok_or_else(|| "--resume requires a path to a snapshot".to_string())?,
+                )
+            }
+            "--profile-out" => {
+                profile_path = Some(args.next().ok_or_else(|| {
+                    "--profile-out requires a path to write the profile to".to_string()
+                })?)
+            }
+            _ => match profile::Format::from_arg(&arg) {
+                Some(f) => profile_format = Some(f?),
+                None => input = Some(arg),
+            },
+        }
+    }
+
+    let resume = resume
+        .map(|path| Snapshot::parse(&fs::read_to_string(path)?))
+        .transpose()?;
+    let (input, bytes) = match (input, &resume) {
+        (Some(input), _) => {
+            let bytes = fs::read(&input)?;
+            (input, bytes)
+        }
+        (None, Some(snap)) => (snap.binary.clone(), snap.program.clone()),
+        (None, None) => return Err("Missing .t8b binary file".into()),
+    };
+
+    let config: config::Config =
+        toml::from_slice(&fs::read(&config_path)?).expect("Failed to parse t8.toml configuration");
+
+    let instructions = from(&bytes)?;
+    for warning in config.validate(&instructions)? {
+        eprintln!("warning: {warning}");
+    }
+    let debug = DebugInfo::load_for(Path::new(&input))?;
+    let lines = debug
+        .as_ref()
+        .and_then(|d| fs::read_to_string(&d.file).ok())
+        .map(|src| src.lines().map(String::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut cpu = cpu::Cpu::new(&config, &instructions, config.memory.initial()?)?;
+    if let Some(debug) = &debug {
+        cpu = cpu.with_debug(debug, &lines);
+    }
+    let mut steps = 0;
+    if let Some(snap) = &resume {
+        cpu.restore(snap)?;
+        steps = snap.steps;
+    }
 
-    let config =
-        toml::from_slice(&fs::read("t8.toml")?).expect("Failed to parse t8.toml configuration");
+    if let Some(addr) = gdb {
+        let mut cpu = cpu.with_undo();
+        return match addr.strip_prefix("unix:") {
+            Some(path) => {
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                eprintln!("Waiting for gdb on {addr}");
+                let (stream, _) = listener.accept()?;
+                gdb::serve(&mut cpu, stream.try_clone()?, stream)
+            }
+            None => {
+                let listener =
+                    std::net::TcpListener::bind(&gdb::listen_addrs(&addr, gdb_expose)?[..])?;
+                eprintln!("Waiting for gdb on {}", listener.local_addr()?);
+                let (stream, _) = listener.accept()?;
+                gdb::serve(&mut cpu, stream.try_clone()?, stream)
+            }
+        };
+    }
+
+    if tui_mode {
+        let mut cpu = cpu.quiet().with_undo();
+        return tui::run(&mut cpu, &config);
+    }
 
-    let instructions = from(&fs::read(&input)?)?;
-    let mut cpu = cpu::Cpu::new(&config, &instructions);
-    while !cpu.halted {
+    if debug_mode {
+        let mut cpu = cpu.with_undo();
+        return debugger::run(&mut cpu, std::io::stdin().lock(), std::io::stdout().lock());
+    }
+
+    // --profile-out alone asks for the default report as well
+    let profile_format = profile_format.or(profile_path.as_ref().map(|_| profile::Format::Human));
+    let mut profile = profile_format.map(|_| profile::Profile::default());
+    let mut coverage = match (&coverage_path, &debug) {
+        (Some(_), Some(_)) => Some(coverage::Coverage::new(instructions.len())),
+        (Some(_), None) => {
+            return Err(format!(
+                "Coverage requires the debug sidecar {}, reassemble the program",
+                shared::debug::sidecar(Path::new(&input)).display()
+            )
+            .into());
+        }
+        _ => None,
+    };
+    let result: Result<(), Box<dyn std::error::Error>> = loop {
+        if let Some((at, path)) = &snapshot_at
+            && *at == steps
+        {
+            let mut buf = Vec::new();
+            cpu.snapshot(&input, &bytes, steps)?.write(&mut buf)?;
+            fs::write(path, buf)?;
+        }
+        if cpu.halted {
+            break Ok(());
+        }
+        let pc = cpu.pc();
         if cpu.step().is_none() {
-            panic!("Failed to walk cpu")
+            let mut msg = format!("Fault at {pc:04x}");
+            match (cpu.device_error(), instructions.get(pc as usize)) {
+                (Some(e), _) => msg.push_str(&format!(", {e}")),
+                (None, Some(Err(invalid))) => msg.push_str(&format!(", {invalid}")),
+                (None, Some(Ok(Instruction::ST { addr } | Instruction::LD { addr }))) => {
+                    msg.push_str(&format!(", 0x{addr:X} is reserved or outside of memory"))
+                }
+                _ => {}
+            }
+            if let Some(loc) = cpu.location(pc) {
+                msg.push_str(&format!(": {loc}"));
+            }
+            break Err(msg.into());
+        }
+        if let (Some(profile), Some(Ok(ins))) = (&mut profile, instructions.get(pc as usize)) {
+            profile.record(pc, ins);
+        }
+        if let Some(coverage) = &mut coverage {
+            coverage.record(pc);
+        }
+        steps += 1;
+    };
+
+    // flushes device output before the report
+    drop(cpu);
+    if let (Some(coverage), Some(path), Some(debug)) = (&coverage, &coverage_path, &debug) {
+        fs::write(path, coverage.lcov(debug))?;
+    }
+    if let (Some(profile), Some(format)) = (profile, profile_format) {
+        let report = match format {
+            profile::Format::Human => profile.human(&config, &instructions),
+            profile::Format::Json => format!("{}\n", profile.json(&config, &instructions)),
+        };
+        match &profile_path {
+            Some(path) => fs::write(path, report)?,
+            None => eprint!("{report}"),
+        }
+    }
+    result?;
+
+    match snapshot_at {
+        Some((at, _)) if at > steps => {
+            Err(format!("Halted after {steps} steps, before the snapshot at step {at}").into())
         }
+        _ => Ok(()),
     }
-    Ok(())
 }
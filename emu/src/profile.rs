@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use serde_json::{Value, json};
+use shared::{asm::Instruction, config::Config, scriptorium::InvalidByte};
+
+use crate::debugger::describe;
+
+/// how the profile is written, selected via `--profile[=<human|json>]`, it goes to stderr or the
+/// path given via `--profile-out <path>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+impl Format {
+    /// parses `--profile` and `--profile=<format>`, returns `None` if `arg` is a different argument
+    pub fn from_arg(arg: &str) -> Option<Result<Self, String>> {
+        if arg == "--profile" {
+            return Some(Ok(Self::Human));
+        }
+        let format = arg.strip_prefix("--profile=")?;
+        Some(match format {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown profile format `{format}`, wanted `human` or `json`"
+            )),
+        })
+    }
+}
+
+/// Counts collected over a run, fed one executed instruction at a time via [`Profile::record`].
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// executed instructions
+    cycles: usize,
+    /// executions per pc
+    pcs: BTreeMap<u8, usize>,
+    /// executions per mnemonic
+    ops: BTreeMap<&'static str, usize>,
+    /// `LD`s per address
+    reads: BTreeMap<u8, usize>,
+    /// `ST`s per address
+    writes: BTreeMap<u8, usize>,
+}
+
+impl Profile {
+    /// records `ins`, executed at `pc`
+    pub fn record(&mut self, pc: u8, ins: &Instruction) {
+        self.cycles += 1;
+        *self.pcs.entry(pc).or_default() += 1;
+        *self.ops.entry(ins.to_str_lossy()).or_default() += 1;
+        match ins {
+            Instruction::LD { addr } => *self.reads.entry(*addr).or_default() += 1,
+            Instruction::ST { addr } => *self.writes.entry(*addr).or_default() += 1,
+            _ => {}
+        }
+    }
+
+    /// pcs with their count, the hottest first
+    fn hot(&self) -> Vec<(u8, usize)> {
+        let mut pcs = self.pcs.iter().map(|(pc, n)| (*pc, *n)).collect::<Vec<_>>();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pcs
+    }
+
+    /// addresses with their reads and writes, in address order
+    fn memory(&self) -> Vec<(u8, usize, usize)> {
+        let mut addrs = self
+            .reads
+            .keys()
+            .chain(self.writes.keys())
+            .collect::<Vec<_>>();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+            .into_iter()
+            .map(|a| {
+                let count = |m: &BTreeMap<u8, usize>| m.get(a).copied().unwrap_or(0);
+                (*a, count(&self.reads), count(&self.writes))
+            })
+            .collect()
+    }
+
+    /// devices of `config` with their address, reads and writes, in address order
+    fn devices<'c>(&self, config: &'c Config) -> Vec<(&'c str, u8, usize, usize)> {
+        let mut devices = config
+            .io
+            .iter()
+            .map(|(name, dev)| {
+                let count = |m: &BTreeMap<u8, usize>| m.get(&dev.addr).copied().unwrap_or(0);
+                (
+                    name.as_str(),
+                    dev.addr,
+                    count(&self.reads),
+                    count(&self.writes),
+                )
+            })
+            .collect::<Vec<_>>();
+        devices.sort_by_key(|(name, addr, ..)| (*addr, *name));
+        devices
+    }
+
+    pub fn human(&self, config: &Config, program: &[Result<Instruction, InvalidByte>]) -> String {
+        let mut out = format!("cycles {}\n\npc    count  instruction\n", self.cycles);
+        for (pc, n) in self.hot() {
+            let ins = match program.get(pc as usize) {
+                Some(Ok(ins)) => describe(ins),
+                _ => String::new(),
+            };
+            out.push_str(&format!("{pc:04x} {n:>6}  {ins}\n"));
+        }
+        out.push_str("\nopcode  count\n");
+        for (op, n) in &self.ops {
+            out.push_str(&format!("{op:<6} {n:>6}\n"));
+        }
+        out.push_str("\naddr  reads  writes\n");
+        for (addr, reads, writes) in self.memory() {
+            out.push_str(&format!("0x{addr:X} {reads:>7} {writes:>7}\n"));
+        }
+        out.push_str("\ndevice      addr reads  writes\n");
+        for (name, addr, reads, writes) in self.devices(config) {
+            out.push_str(&format!("{name:<10}  0x{addr:X} {reads:>6} {writes:>7}\n"));
+        }
+        out
+    }
+
+    pub fn json(&self, config: &Config, program: &[Result<Instruction, InvalidByte>]) -> Value {
+        json!({
+            "cycles": self.cycles,
+            "pcs": self.hot().into_iter().map(|(pc, n)| json!({
+                "pc": pc,
+                "count": n,
+                "instruction": match program.get(pc as usize) {
+                    Some(Ok(ins)) => Value::from(describe(ins)),
+                    _ => Value::Null,
+                },
+            })).collect::<Vec<_>>(),
+            "opcodes": self.ops,
+            "memory": self.memory().into_iter().map(|(addr, reads, writes)| json!({
+                "addr": addr,
+                "reads": reads,
+                "writes": writes,
+            })).collect::<Vec<_>>(),
+            "devices": self.devices(config).into_iter().map(|(name, addr, reads, writes)| json!({
+                "name": name,
+                "addr": addr,
+                "reads": reads,
+                "writes": writes,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::asm::Instruction::*;
+
+    #[test]
+    fn test_profile() {
+        let config = Config::with_device(
+            "led",
+            shared::config::Device {
+                addr: 0xF,
+                ..Default::default()
+            },
+        );
+        let program = [
+            LOADI { imm: 1 },
+            ST { addr: 0xF },
+            LD { addr: 0xF },
+            ST { addr: 3 },
+        ]
+        .map(Ok);
+        let mut profile = Profile::default();
+        for (pc, ins) in [0, 1, 2, 1, 3].into_iter().map(|pc| (pc, &program[pc])) {
+            profile.record(pc as u8, ins.as_ref().unwrap());
+        }
+
+        let json = profile.json(&config, &program);
+        assert_eq!(json["cycles"], 5);
+        assert_eq!(
+            json["pcs"][0],
+            json!({"pc": 1, "count": 2, "instruction": "ST [0xF]"})
+        );
+        assert_eq!(json["opcodes"], json!({"LD": 1, "LOADI": 1, "ST": 3}));
+        assert_eq!(
+            json["memory"][1],
+            json!({"addr": 15, "reads": 1, "writes": 2})
+        );
+        assert_eq!(
+            json["devices"],
+            json!([{"name": "led", "addr": 15, "reads": 1, "writes": 2}])
+        );
+        assert!(
+            profile
+                .human(&config, &program)
+                .contains("led         0xF      1       2\n")
+        );
+    }
+}
@@ -0,0 +1,136 @@
+/// first line of every snapshot
+pub const MAGIC: &str = "t8snap";
+
+/// Full machine state of a [`crate::cpu::Cpu`] after `steps` steps, written as `<name>.t8s`:
+///
+/// ```text
+/// t8snap
+/// binary examples/led.t8b
+/// program 74 38 63 70 75 11 5F 10 5F 1D 5F 80
+/// steps 4
+/// pc 4
+/// ac 0
+/// dest 0
+/// halted 0
+/// mem 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+/// dev led 2
+/// ```
+///
+/// The program is embedded, so a snapshot resumes without the binary. `dev` lines hold the
+/// position of each device, the amount of bytes written to its file or the state of an `rng`.
+/// Longer files are truncated on resume.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// path the program was loaded from, its debug sidecar is picked up on resume if it exists
+    pub binary: String,
+    pub program: Vec<u8>,
+    pub steps: usize,
+    pub pc: u8,
+    pub ac: u8,
+    pub dest: u8,
+    pub halted: bool,
+    pub mem: Vec<u8>,
+    pub devices: Vec<(String, u64)>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Snapshot {
+    pub fn write<W: std::io::Write>(&self, w: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(w, "{MAGIC}")?;
+        writeln!(w, "binary {}", self.binary)?;
+        writeln!(w, "program {}", hex(&self.program))?;
+        writeln!(w, "steps {}", self.steps)?;
+        writeln!(w, "pc {}", self.pc)?;
+        writeln!(w, "ac {}", self.ac)?;
+        writeln!(w, "dest {}", self.dest)?;
+        writeln!(w, "halted {}", self.halted as u8)?;
+        writeln!(w, "mem {}", hex(&self.mem))?;
+        for (name, pos) in &self.devices {
+            writeln!(w, "dev {name} {pos}")?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(src: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut lines = src.lines().enumerate();
+        if lines.next().map(|(_, l)| l) != Some(MAGIC) {
+            return Err("Invalid snapshot header".into());
+        }
+
+        let mut snap = Snapshot::default();
+        for (i, line) in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let bytes = || -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+                Ok(value
+                    .split_whitespace()
+                    .map(|b| u8::from_str_radix(b, 16))
+                    .collect::<Result<_, _>>()?)
+            };
+            match key {
+                "binary" => snap.binary = value.to_string(),
+                "program" => snap.program = bytes()?,
+                "steps" => snap.steps = value.parse()?,
+                "pc" => snap.pc = value.parse()?,
+                "ac" => snap.ac = value.parse()?,
+                "dest" => snap.dest = value.parse()?,
+                "halted" => snap.halted = value == "1",
+                "mem" => snap.mem = bytes()?,
+                "dev" => {
+                    let (name, pos) = value
+                        .split_once(' ')
+                        .ok_or_else(|| format!("Missing position in snapshot line {}", i + 1))?;
+                    snap.devices.push((name.to_string(), pos.parse()?));
+                }
+                "" => {}
+                other => {
+                    return Err(
+                        format!("Unknown snapshot entry `{other}` in line {}", i + 1).into(),
+                    );
+                }
+            }
+        }
+        Ok(snap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_parse_roundtrip() {
+        let mut mem = vec![0; 16];
+        mem[0xF] = 0xD;
+        let snap = Snapshot {
+            binary: "examples/led.t8b".into(),
+            program: vec![0x74, 0x38, 0x63, 0x70, 0x75, 0x11, 0x5F, 0x80],
+            steps: 2,
+            pc: 2,
+            ac: 1,
+            dest: 0,
+            halted: false,
+            mem,
+            devices: vec![("led".into(), 1)],
+        };
+
+        let mut buf = Vec::new();
+        snap.write(&mut buf).unwrap();
+        assert_eq!(
+            Snapshot::parse(str::from_utf8(&buf).unwrap()).unwrap(),
+            snap
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Snapshot::parse("t8dbg\npc 1").is_err());
+        assert!(Snapshot::parse("t8snap\nmem 00 0G").is_err());
+    }
+}
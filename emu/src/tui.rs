@@ -0,0 +1,413 @@
+use std::{io::Write, time::Duration};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::Stylize,
+    terminal::{self, ClearType},
+};
+use shared::{
+    asm::Instruction,
+    config::{Config, Kind},
+};
+
+use crate::{cpu::Cpu, debugger::describe, device};
+
+/// amount of program lines shown around pc
+const PROGRAM_LINES: usize = 16;
+/// writes younger than this many steps are highlighted in the memory grid
+const RECENT: usize = 3;
+
+const KEYS: &str = "s step  b back  r run/pause  x reset  q quit";
+
+/// `len` characters of `s`, cut or padded with spaces
+fn fit(s: &str, len: usize) -> String {
+    format!("{:<len$.len$}", s)
+}
+
+struct Tui<'t, 'cpu> {
+    cpu: &'t mut Cpu<'cpu>,
+    config: &'t Config,
+    /// steps taken since the ui opened, `x` rewinds to there
+    steps: usize,
+    /// every ST executed as (step, addr, value), rolled back alongside the cpu
+    writes: Vec<(usize, u8, u8)>,
+    running: bool,
+    fault: bool,
+}
+
+impl Tui<'_, '_> {
+    fn step(&mut self) {
+        if self.cpu.halted || self.fault {
+            self.running = false;
+            return;
+        }
+        let store = match self.cpu.instruction(self.cpu.pc()) {
+            Some(Ok(Instruction::ST { addr })) => Some(*addr),
+            _ => None,
+        };
+        if self.cpu.step().is_none() {
+            self.fault = true;
+            self.running = false;
+            return;
+        }
+        if let Some(addr) = store {
+            let value = self.cpu.mem()[addr as usize];
+            self.writes.push((self.steps, addr, value));
+        }
+        self.steps += 1;
+    }
+
+    fn back(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.cpu.step_back()? {
+            return Ok(false);
+        }
+        self.fault = false;
+        self.steps -= 1;
+        self.writes.retain(|(step, ..)| *step < self.steps);
+        Ok(true)
+    }
+
+    fn program(&self) -> Vec<String> {
+        let pc = self.cpu.pc() as usize;
+        let start = pc.saturating_sub(PROGRAM_LINES / 2);
+        (start..start + PROGRAM_LINES)
+            .map(|i| {
+                let Some(ins) = self
+                    .cpu
+                    .instruction(i as u8)
+                    .filter(|_| i <= u8::MAX as usize)
+                else {
+                    return fit("", 24);
+                };
+                let ins = match ins {
+                    Ok(ins) => describe(ins),
+                    Err(invalid) => format!(".byte 0x{:02X}", invalid.byte),
+                };
+                let line = fit(
+                    &format!("{} {i:04x} {ins}", if i == pc { ">" } else { " " }),
+                    24,
+                );
+                if i == pc {
+                    line.reverse().to_string()
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+
+    fn registers(&self) -> Vec<String> {
+        let ir = match self.cpu.instruction(self.cpu.pc()) {
+            Some(Ok(ins)) => ins.encode().unwrap_or(0),
+            Some(Err(invalid)) => invalid.byte,
+            None => 0,
+        };
+        let state = if self.fault {
+            "fault".red().to_string()
+        } else if self.cpu.halted {
+            "halted".yellow().to_string()
+        } else if self.running {
+            "running".green().to_string()
+        } else {
+            "paused".to_string()
+        };
+        vec![
+            format!("pc   0x{:02X}", self.cpu.pc()),
+            format!("ac   0x{:02X}", self.cpu.ac()),
+            format!("dest 0x{:02X}", self.cpu.dest()),
+            format!("ir   0x{ir:02X}"),
+            format!("step {}", self.steps),
+            state,
+        ]
+    }
+
+    /// memory as a hex grid of 4 bytes per row, cells written in the last [`RECENT`] steps are highlighted
+    fn memory(&self) -> Vec<String> {
+        let recent = |addr: usize| {
+            self.writes
+                .iter()
+                .rev()
+                .take_while(|(step, ..)| step + RECENT >= self.steps)
+                .any(|(_, a, _)| *a as usize == addr)
+        };
+        self.cpu
+            .mem()
+            .chunks(4)
+            .enumerate()
+            .map(|(row, chunk)| {
+                let cells = chunk
+                    .iter()
+                    .enumerate()
+                    .map(|(col, value)| {
+                        let addr = row * 4 + col;
+                        let cell = format!("{value:02X}");
+                        if recent(addr) {
+                            cell.black().on_yellow().to_string()
+                        } else {
+                            cell
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("{:X}: {}", row * 4, cells.join(" "))
+            })
+            .collect()
+    }
+
+    /// a panel per device: an LED bar for `led8`, the display for `sevenseg`, the count of a
+    /// `timer` and a text console for plain files, the console and sockets
+    fn devices(&self) -> Vec<String> {
+        let mut devices = self.config.io.iter().collect::<Vec<_>>();
+        devices.sort_by_key(|(name, dev)| (dev.addr, name.as_str()));
+        devices
+            .into_iter()
+            .flat_map(|(name, dev)| {
+                let mut written = self
+                    .writes
+                    .iter()
+                    .filter(|(_, addr, _)| *addr == dev.addr)
+                    .map(|(.., value)| *value);
+                let panel = match dev.kind {
+                    Kind::Led8 => {
+                        let value = written.next_back().unwrap_or(0);
+                        vec![device::led8(value).replace('●', &"●".red().to_string())]
+                    }
+                    Kind::Sevenseg => {
+                        let value = written.next_back().unwrap_or(0);
+                        let mask = if dev.segments {
+                            value
+                        } else {
+                            device::digit(value)
+                        };
+                        device::sevenseg(mask).map(|l| l.red().to_string()).to_vec()
+                    }
+                    Kind::Timer => {
+                        let ticks = self.cpu.cycles() / dev.prescaler.unwrap_or(1).max(1);
+                        vec![format!("{:02X}", ticks as u8)]
+                    }
+                    Kind::Rng => vec![String::new()],
+                    Kind::File | Kind::Console | Kind::Socket => {
+                        let text = written
+                            .map(|b| {
+                                if b.is_ascii_graphic() || b == b' ' {
+                                    b as char
+                                } else {
+                                    '.'
+                                }
+                            })
+                            .collect::<String>();
+                        // the end of the output is the interesting part
+                        vec![
+                            text.chars()
+                                .skip(text.chars().count().saturating_sub(40))
+                                .collect(),
+                        ]
+                    }
+                };
+                let label = fit(&format!("{name} 0x{:X}", dev.addr), 12);
+                panel
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, line)| {
+                        let label = if i == 0 { label.clone() } else { fit("", 12) };
+                        format!("{label} {line}")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// the whole screen, one string per line
+    fn frame(&self) -> Vec<String> {
+        let program = self.program();
+        let right = ["registers".bold().to_string()]
+            .into_iter()
+            .chain(self.registers())
+            .chain(["".into(), "memory".bold().to_string()])
+            .chain(self.memory())
+            .collect::<Vec<_>>();
+
+        let mut lines = vec![format!("{}  {}", "t8 emu".bold(), KEYS.dark_grey())];
+        lines.push("program".bold().to_string());
+        for i in 0..program.len().max(right.len()) {
+            lines.push(format!(
+                "{}   {}",
+                program.get(i).cloned().unwrap_or_else(|| fit("", 24)),
+                right.get(i).cloned().unwrap_or_default()
+            ));
+        }
+        lines.push(String::new());
+        lines.push("devices".bold().to_string());
+        lines.extend(self.devices());
+        lines
+    }
+}
+
+fn draw<W: Write>(w: &mut W, frame: &[String]) -> std::io::Result<()> {
+    queue!(w, terminal::Clear(ClearType::All))?;
+    for (i, line) in frame.iter().enumerate() {
+        queue!(w, cursor::MoveTo(0, i as u16))?;
+        write!(w, "{line}")?;
+    }
+    w.flush()
+}
+
+/// raw mode and the alternate screen while alive, restored on drop so an error or a panic does
+/// not leave the terminal unusable
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = Terminal;
+        execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            std::io::stdout(),
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs the terminal ui until `q`, the cpu has to record undo information for stepping back and
+/// resetting, see [`Cpu::with_undo`].
+pub fn run(cpu: &mut Cpu, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tui = Tui {
+        cpu,
+        config,
+        steps: 0,
+        writes: vec![],
+        running: false,
+        fault: false,
+    };
+
+    let mut stdout = std::io::stdout();
+    let _terminal = Terminal::enter()?;
+    loop {
+        draw(&mut stdout, &tui.frame())?;
+        let tick = Duration::from_millis(if tui.running { 100 } else { 500 });
+        if !event::poll(tick)? {
+            if tui.running {
+                tui.step();
+            }
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('s') | KeyCode::Right => tui.step(),
+            KeyCode::Char('b') | KeyCode::Left => {
+                tui.back()?;
+            }
+            KeyCode::Char('r') | KeyCode::Char(' ') => tui.running = !tui.running,
+            KeyCode::Char('x') => {
+                tui.running = false;
+                while tui.back()? {}
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::asm::Instruction::*;
+    use shared::config::Device;
+
+    /// the frame without styling
+    fn plain(frame: &[String]) -> String {
+        let mut out = String::new();
+        let mut chars = frame.join("\n").chars().collect::<Vec<_>>().into_iter();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_frame_shows_state_and_devices() {
+        let config = Config::with_device(
+            "led",
+            Device {
+                addr: 0xF,
+                kind: Kind::Led8,
+                ..Default::default()
+            },
+        );
+        let ins = [LOADI { imm: 5 }, ST { addr: 0xF }, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        let mut tui = Tui {
+            cpu: &mut cpu,
+            config: &config,
+            steps: 0,
+            writes: vec![],
+            running: false,
+            fault: false,
+        };
+        tui.step();
+        tui.step();
+        assert_eq!(tui.writes, vec![(1, 0xF, 5)]);
+
+        let frame = plain(&tui.frame());
+        assert!(frame.contains("> 0002 HALT"), "{frame}");
+        assert!(frame.contains("ac   0x05"), "{frame}");
+        assert!(frame.contains("C: 00 00 00 05"), "{frame}");
+        assert!(frame.contains("led 0xF      ○○○○○●○●"), "{frame}");
+
+        tui.back().unwrap();
+        assert!(tui.writes.is_empty());
+        assert_eq!(tui.cpu.pc(), 1);
+    }
+
+    #[test]
+    fn test_timer_panel_follows_cpu_cycles() {
+        let config = Config::with_device(
+            "timer",
+            Device {
+                addr: 0xE,
+                kind: Kind::Timer,
+                prescaler: Some(2),
+                ..Default::default()
+            },
+        );
+        let ins = [NOP, NOP, NOP, NOP, NOP, HALT].map(Ok);
+        let mut cpu = Cpu::for_test(&config, &ins);
+        // as if resumed from a snapshot taken after 2 steps
+        cpu.step();
+        cpu.step();
+        let mut tui = Tui {
+            cpu: &mut cpu,
+            config: &config,
+            steps: 0,
+            writes: vec![],
+            running: false,
+            fault: false,
+        };
+        tui.step();
+        tui.step();
+        let frame = plain(&tui.frame());
+        assert!(frame.contains("timer 0xE    02"), "{frame}");
+    }
+}
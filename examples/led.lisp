@@ -2,8 +2,8 @@
 ;
 ; One to one port of examples/led.t8
 ;
-; Compile via: cargo run -p lisp examples/led.lisp
-; Emulate via: cargo run -p emu examples/led.lisp.t8b
+; Compile via: cargo run -p cc examples/led.lisp
+; Emulate via: cargo run -p emu examples/led.t8b
 
 ; constants are resolved at compile time
 (const led 0xF)
@@ -0,0 +1,308 @@
+use r#as::{
+    lexer::{Lexer, Token, TokenInner},
+    parser::Parser,
+};
+use shared::{asm::Instruction, err::T8Err};
+
+/// indentation of instructions, directives and comment lines start at column 0
+const INDENT: &str = "    ";
+/// mnemonics are padded to the longest one, so operands start at the same column
+const MNEMONIC_WIDTH: usize = 5;
+
+enum Stmt {
+    Const {
+        name: String,
+        value: String,
+    },
+    /// `.byte` and `.load`
+    Builtin {
+        name: String,
+        value: String,
+    },
+    Instruction {
+        mnemonic: String,
+        operand: Option<String>,
+    },
+}
+
+enum Line {
+    Blank,
+    Comment(String),
+    Stmt { stmt: Stmt, comment: Option<String> },
+}
+
+/// source text of the token, numbers are normalized to uppercase hex without leading zeros if
+/// they were written in hex and decimal otherwise
+fn text(lines: &[&[u8]], tok: &Token) -> String {
+    let raw = lines
+        .get(tok.line)
+        .and_then(|l| l.get(tok.col..tok.col + tok.len))
+        .unwrap_or_default();
+    match tok.inner {
+        TokenInner::Number(n) if raw.starts_with(b"0x") => format!("0x{n:X}"),
+        TokenInner::Number(n) => n.to_string(),
+        _ => String::from_utf8_lossy(raw).into_owned(),
+    }
+}
+
+/// Formats `src` into its canonical layout. Only syntactically valid source is formatted, the
+/// parser error is returned otherwise.
+pub fn format(src: &[u8]) -> Result<String, T8Err> {
+    Parser::new(&Lexer::new(src).lex()?).parse()?;
+
+    let tokens = Lexer::new(src).with_comments().lex()?;
+    let lines = src.split(|b| *b == b'\n').collect::<Vec<_>>();
+
+    let mut out: Vec<Line> = vec![];
+    // line of the last token consumed, to keep blank lines and attach trailing comments
+    let mut last_line = None;
+    let mut i = 0;
+    while let Some(tok) = tokens.get(i) {
+        if last_line.is_some_and(|l| tok.line > l + 1) {
+            out.push(Line::Blank);
+        }
+
+        if let TokenInner::Comment(_) = tok.inner {
+            let comment = text(&lines, tok);
+            match out.last_mut() {
+                Some(Line::Stmt { comment: c, .. }) if last_line == Some(tok.line) => {
+                    *c = Some(comment)
+                }
+                _ => out.push(Line::Comment(comment)),
+            }
+            last_line = Some(tok.line);
+            i += 1;
+            continue;
+        }
+
+        // the parser accepted the source, so every statement is well formed
+        let arg = |n: usize| {
+            tokens
+                .get(i + n)
+                .map(|t| text(&lines, t))
+                .unwrap_or_default()
+        };
+        let (stmt, consumed) = match tok.inner {
+            TokenInner::Builtin(b"const") => (
+                Stmt::Const {
+                    name: arg(1),
+                    value: arg(2),
+                },
+                3,
+            ),
+            TokenInner::Builtin(b"load")
+                if tokens.get(i + 2).is_some_and(|t| {
+                    t.line == tok.line && t.inner == TokenInner::Ident(b"keep")
+                }) =>
+            {
+                (
+                    Stmt::Builtin {
+                        name: text(&lines, tok),
+                        value: format!("{} keep", arg(1)),
+                    },
+                    3,
+                )
+            }
+            TokenInner::Builtin(_) => (
+                Stmt::Builtin {
+                    name: text(&lines, tok),
+                    value: arg(1),
+                },
+                2,
+            ),
+            _ => {
+                let mnemonic = text(&lines, tok).to_ascii_uppercase();
+                let partial = Instruction::from_str_lossy(&mnemonic).ok();
+                match partial.as_ref().and_then(|p| p.operand()) {
+                    None => (
+                        Stmt::Instruction {
+                            mnemonic,
+                            operand: None,
+                        },
+                        1,
+                    ),
+                    Some(kind) => {
+                        let (inner, consumed) = match tokens.get(i + 1).map(|t| t.inner) {
+                            Some(TokenInner::Hash) => (arg(2), 3),
+                            Some(TokenInner::LeftBraket) => (arg(2), 4),
+                            _ => (arg(1), 2),
+                        };
+                        let operand = match kind {
+                            "addr" => format!("[{inner}]"),
+                            _ => format!("#{inner}"),
+                        };
+                        (
+                            Stmt::Instruction {
+                                mnemonic,
+                                operand: Some(operand),
+                            },
+                            consumed,
+                        )
+                    }
+                }
+            }
+        };
+        i += consumed;
+        last_line = tokens.get(i - 1).map(|t| t.line);
+        out.push(Line::Stmt {
+            stmt,
+            comment: None,
+        });
+    }
+
+    Ok(render(&out))
+}
+
+fn render(lines: &[Line]) -> String {
+    let mut rendered = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        match &lines[i] {
+            Line::Blank => {
+                if rendered.last().is_some_and(|l: &String| !l.is_empty()) {
+                    rendered.push(String::new());
+                }
+                i += 1;
+            }
+            Line::Comment(c) => {
+                rendered.push(c.clone());
+                i += 1;
+            }
+            Line::Stmt { .. } => {
+                // a block is a run of statements, constants and trailing comments align per block
+                let end = lines[i..]
+                    .iter()
+                    .position(|l| !matches!(l, Line::Stmt { .. }))
+                    .map(|p| i + p)
+                    .unwrap_or(lines.len());
+                let block = &lines[i..end];
+
+                let name_width = block
+                    .iter()
+                    .filter_map(|l| match l {
+                        Line::Stmt {
+                            stmt: Stmt::Const { name, .. },
+                            ..
+                        } => Some(name.len()),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+
+                let code = block
+                    .iter()
+                    .map(|l| match l {
+                        Line::Stmt { stmt, .. } => match stmt {
+                            Stmt::Const { name, value } => {
+                                format!(".const {name:<name_width$} {value}")
+                            }
+                            Stmt::Builtin { name, value } => format!("{name} {value}"),
+                            Stmt::Instruction {
+                                mnemonic,
+                                operand: Some(operand),
+                            } => format!("{INDENT}{mnemonic:<MNEMONIC_WIDTH$} {operand}"),
+                            Stmt::Instruction {
+                                mnemonic,
+                                operand: None,
+                            } => format!("{INDENT}{mnemonic}"),
+                        },
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>();
+
+                let comment_col = block
+                    .iter()
+                    .zip(&code)
+                    .filter(|(l, _)| {
+                        matches!(
+                            l,
+                            Line::Stmt {
+                                comment: Some(_),
+                                ..
+                            }
+                        )
+                    })
+                    .map(|(_, c)| c.len())
+                    .max()
+                    .unwrap_or(0);
+
+                for (line, code) in block.iter().zip(code) {
+                    rendered.push(match line {
+                        Line::Stmt {
+                            comment: Some(comment),
+                            ..
+                        } => format!("{code:<comment_col$} {comment}"),
+                        _ => code,
+                    });
+                }
+                i = end;
+            }
+        }
+    }
+
+    while rendered.last().is_some_and(|l| l.is_empty()) {
+        rendered.pop();
+    }
+    // nothing to format stays empty instead of becoming a lone newline
+    if rendered.is_empty() {
+        return String::new();
+    }
+    let mut out = rendered.join("\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_layout() {
+        let src = b"\n; vim: filetype=asm\n.const led 0x0F ; device\n.const on 01\n\n\n\nloadi #on ; turn on\nST [led]      ; write\nrol 4\nMOV\n  halt\n.load  0x048\n.load 3   keep\n\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "\
+; vim: filetype=asm
+.const led 0xF ; device
+.const on  1
+
+    LOADI #on   ; turn on
+    ST    [led] ; write
+    ROL   #4
+    MOV
+    HALT
+.load 0x48
+.load 3 keep
+"
+        );
+    }
+
+    #[test]
+    fn test_empty_source_stays_empty() {
+        for src in [&b""[..], b"\n", b"\r\n\n\t\n"] {
+            assert_eq!(format(src).unwrap(), "", "{src:?}");
+        }
+    }
+
+    #[test]
+    fn test_statements_on_one_line_are_split() {
+        assert_eq!(
+            format(b"LOADI #1 MOV ; both").unwrap(),
+            "    LOADI #1\n    MOV ; both\n"
+        );
+    }
+
+    #[test]
+    fn test_idempotent_on_examples() {
+        for example in ["led", "fib", "hello", "math"] {
+            let src = std::fs::read(format!("../examples/{example}.t8")).unwrap();
+            let once = format(&src).unwrap();
+            assert_eq!(once, format(once.as_bytes()).unwrap(), "{example}");
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_source() {
+        assert!(format(b"LOADI").is_err());
+    }
+}
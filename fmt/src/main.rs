@@ -0,0 +1,105 @@
+use std::{fs, io::BufRead};
+
+use shared::err::MessageFormat;
+
+mod format;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut check = false;
+    let mut format = MessageFormat::default();
+    let mut inputs = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => match MessageFormat::from_arg(&arg) {
+                Some(f) => format = f?,
+                None => inputs.push(arg),
+            },
+        }
+    }
+    if inputs.is_empty() {
+        return Err("Missing .t8 asm file".into());
+    }
+
+    let mut failed = false;
+    for input in &inputs {
+        let bytes = fs::read(input)?;
+        let formatted = match format::format(&bytes) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                let lines = bytes.lines().map_while(Result::ok).collect::<Vec<_>>();
+                format.emit(&e, input, &lines)?;
+                failed = true;
+                continue;
+            }
+        };
+        if formatted.as_bytes() == bytes {
+            continue;
+        }
+
+        if check {
+            let (line, was, is) = difference(&bytes, formatted.as_bytes());
+            eprintln!("Would reformat {input}:{}", line + 1);
+            if let Some(was) = was {
+                eprintln!("  - {was}");
+            }
+            if let Some(is) = is {
+                eprintln!("  + {is}");
+            }
+            failed = true;
+        } else {
+            fs::write(input, formatted)?;
+        }
+    }
+
+    if failed {
+        return Err(if check {
+            "Some files are not formatted"
+        } else {
+            "Failed to format"
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// 0-based number of the first line that differs and that line on both sides, a side is `None`
+/// when it has no such line, line endings are kept escaped so a `\r` or a missing final newline
+/// is visible
+fn difference(was: &[u8], is: &[u8]) -> (usize, Option<String>, Option<String>) {
+    let split = |src: &[u8]| {
+        src.split_inclusive(|b| *b == b'\n')
+            .map(|l| String::from_utf8_lossy(l).escape_debug().to_string())
+            .collect::<Vec<_>>()
+    };
+    let (was, is) = (split(was), split(is));
+    let line = (0..)
+        .find(|i| was.get(*i) != is.get(*i))
+        .expect("the sources differ");
+    (line, was.get(line).cloned(), is.get(line).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difference_points_at_first_changed_line() {
+        assert_eq!(
+            difference(b"HALT\n  mov\n", b"HALT\n    MOV\n"),
+            (1, Some("  mov\\n".into()), Some("    MOV\\n".into()))
+        );
+        assert_eq!(
+            difference(b"HALT\r\n", b"HALT\n"),
+            (0, Some("HALT\\r\\n".into()), Some("HALT\\n".into()))
+        );
+        assert_eq!(
+            difference(b"HALT\n\n", b"HALT\n"),
+            (1, Some("\\n".into()), None)
+        );
+        assert_eq!(
+            difference(b"HALT", b"HALT\n"),
+            (0, Some("HALT".into()), Some("HALT\\n".into()))
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use r#as::lexer::{Lexer, Token, TokenInner};
+use shared::{asm::Instruction, err::T8Err};
+
+/// `.const` definition found in a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Def<'d> {
+    pub name: &'d str,
+    pub value: Option<u8>,
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+/// token types in the order advertised via the semantic tokens legend
+pub const TOKEN_TYPES: [&str; 6] = [
+    "keyword", "variable", "number", "macro", "operator", "comment",
+];
+/// token modifiers in the order advertised via the semantic tokens legend
+pub const TOKEN_MODIFIERS: [&str; 1] = ["declaration"];
+
+/// unit columns are counted in on the wire, the lexer counts bytes, see [`Document::client_col`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// the lsp default, used unless the client offers `utf-8` in `initialize`
+    #[default]
+    Utf16,
+    Utf8,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Utf16 => "utf-16",
+            Encoding::Utf8 => "utf-8",
+        }
+    }
+
+    fn units(&self, c: char) -> usize {
+        match self {
+            Encoding::Utf16 => c.len_utf16(),
+            Encoding::Utf8 => c.len_utf8(),
+        }
+    }
+}
+
+/// Lexed view of a document. Lines are lexed one by one, so a single line the lexer rejects does
+/// not take hover, completion and highlighting for the rest of the document with it.
+pub struct Document<'d> {
+    src: &'d str,
+    tokens: Vec<Token<'d>>,
+    defs: Vec<Def<'d>>,
+}
+
+impl<'d> Document<'d> {
+    pub fn new(src: &'d str) -> Self {
+        let tokens = src
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                Lexer::new(line.as_bytes())
+                    .with_comments()
+                    .lex()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(move |t| Token { line: i, ..t })
+            })
+            .collect::<Vec<_>>();
+
+        let defs = tokens
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, w)| match (w[0].inner, w[1].inner) {
+                (TokenInner::Builtin(b"const"), TokenInner::Ident(name)) => Some(Def {
+                    name: str::from_utf8(name).ok()?,
+                    value: match tokens.get(i + 2).map(|t| t.inner) {
+                        Some(TokenInner::Number(n)) => Some(n),
+                        _ => None,
+                    },
+                    line: w[1].line,
+                    col: w[1].col,
+                    len: w[1].len,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Document { src, tokens, defs }
+    }
+
+    /// Column in `encoding` of the byte column `col` on `line`. Columns past the end of the line,
+    /// like a diagnostic pointing behind the last character, stay that far past it.
+    pub fn client_col(&self, encoding: Encoding, line: usize, col: usize) -> usize {
+        let text = self.src.lines().nth(line).unwrap_or_default();
+        let units = text
+            .char_indices()
+            .take_while(|(i, _)| *i < col)
+            .map(|(_, c)| encoding.units(c))
+            .sum::<usize>();
+        units + col.saturating_sub(text.len())
+    }
+
+    /// byte column of the column `col` in `encoding` on `line`, the inverse of
+    /// [`Document::client_col`]
+    pub fn byte_col(&self, encoding: Encoding, line: usize, col: usize) -> usize {
+        let text = self.src.lines().nth(line).unwrap_or_default();
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            if units >= col {
+                return i;
+            }
+            units += encoding.units(c);
+        }
+        text.len() + col.saturating_sub(units)
+    }
+
+    /// every error and warning the assembler reports for the document
+    pub fn diagnostics(&self, file: &str) -> Vec<T8Err> {
+        let src = self.src.as_bytes();
+        match r#as::assemble(src, file) {
+            // the memory map is unknown here, so no address counts as mapped
+            Ok(_) => r#as::lint::lint(src, None).unwrap_or_default(),
+            Err(e) => vec![e],
+        }
+    }
+
+    /// token under the cursor, or the token directly before it if the cursor is at its end
+    fn token_at(&self, line: usize, col: usize) -> Option<&Token<'d>> {
+        let on_line = || self.tokens.iter().filter(|t| t.line == line);
+        on_line()
+            .find(|t| t.col <= col && col < t.col + t.len)
+            .or_else(|| on_line().find(|t| col == t.col + t.len))
+    }
+
+    /// the last definition of `name`, later definitions shadow earlier ones
+    fn def(&self, name: &[u8]) -> Option<&Def<'d>> {
+        self.defs.iter().rev().find(|d| d.name.as_bytes() == name)
+    }
+
+    /// markdown describing the instruction or constant at the position
+    pub fn hover(&self, line: usize, col: usize) -> Option<String> {
+        let TokenInner::Ident(name) = self.token_at(line, col)?.inner else {
+            return None;
+        };
+        if let Ok(i) = Instruction::from_str_lossy(str::from_utf8(name).ok()?) {
+            let operand = i.operand();
+            let encoding = match operand {
+                Some(operand) => format!("0x{:X}<{operand}>", i.op() >> 4),
+                None => format!("0x{:02X}", i.op()),
+            };
+            return Some(format!(
+                "```asm\n{}{}\n```\n{}\n\nencoding: `{encoding}`",
+                i.to_str_lossy(),
+                operand.map(|o| format!(" <{o}>")).unwrap_or_default(),
+                i.description(),
+            ));
+        }
+        let def = self.def(name)?;
+        Some(match def.value {
+            Some(value) => format!(
+                "```asm\n.const {} 0x{value:X}\n```\n{value} (0b{value:04b})",
+                def.name
+            ),
+            None => format!("```asm\n.const {}\n```", def.name),
+        })
+    }
+
+    /// (line, col, len) of the `.const` definition for the constant at the position
+    pub fn definition(&self, line: usize, col: usize) -> Option<(usize, usize, usize)> {
+        let TokenInner::Ident(name) = self.token_at(line, col)?.inner else {
+            return None;
+        };
+        self.def(name).map(|d| (d.line, d.col, d.len))
+    }
+
+    /// mnemonics and constants as (label, detail, is_constant)
+    pub fn completion(&self) -> Vec<(String, String, bool)> {
+        let mut items = Instruction::ALL
+            .iter()
+            .map(|i| {
+                (
+                    i.to_str_lossy().to_string(),
+                    i.description().to_string(),
+                    false,
+                )
+            })
+            .collect::<Vec<_>>();
+        for def in &self.defs {
+            if items.iter().any(|(label, ..)| label == def.name) {
+                continue;
+            }
+            let detail = def
+                .value
+                .map(|v| format!(".const {} 0x{v:X}", def.name))
+                .unwrap_or_default();
+            items.push((def.name.to_string(), detail, true));
+        }
+        items
+    }
+
+    /// semantic tokens in the relative encoding of the lsp spec, indexes refer to
+    /// [`TOKEN_TYPES`] and [`TOKEN_MODIFIERS`], columns and lengths are counted in `encoding`
+    pub fn semantic_tokens(&self, encoding: Encoding) -> Vec<u32> {
+        let mut data = Vec::with_capacity(self.tokens.len() * 5);
+        let (mut prev_line, mut prev_col) = (0, 0);
+        for (i, tok) in self.tokens.iter().enumerate() {
+            let declaration = i > 0
+                && self.tokens[i - 1].inner == TokenInner::Builtin(b"const")
+                && self.tokens[i - 1].line == tok.line;
+            let (kind, modifiers) = match tok.inner {
+                TokenInner::Ident(name) => {
+                    let mnemonic =
+                        str::from_utf8(name).is_ok_and(|n| Instruction::from_str_lossy(n).is_ok());
+                    if mnemonic && !declaration {
+                        (0, 0)
+                    } else {
+                        (1, declaration as u32)
+                    }
+                }
+                TokenInner::Number(_) => (2, 0),
+                TokenInner::Builtin(_) => (3, 0),
+                TokenInner::Hash | TokenInner::LeftBraket | TokenInner::RightBraket => (4, 0),
+                TokenInner::Comment(_) => (5, 0),
+            };
+
+            let col = self.client_col(encoding, tok.line, tok.col);
+            let len = self.client_col(encoding, tok.line, tok.col + tok.len) - col;
+            let delta_line = tok.line - prev_line;
+            let delta_col = if delta_line == 0 { col - prev_col } else { col };
+            data.extend([
+                delta_line as u32,
+                delta_col as u32,
+                len as u32,
+                kind,
+                modifiers,
+            ]);
+            (prev_line, prev_col) = (tok.line, col);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = ".const led 0xF\n\n    LOADI #1 ; on\n    ST [led]\n";
+
+    #[test]
+    fn test_hover_instruction_and_constant() {
+        let doc = Document::new(SRC);
+        let hover = doc.hover(2, 5).unwrap();
+        assert!(hover.contains("LOADI <imm>"), "{hover}");
+        assert!(hover.contains("Load immediate into AC"), "{hover}");
+        assert!(doc.hover(3, 9).unwrap().contains(".const led 0xF"));
+        assert_eq!(doc.hover(1, 0), None);
+    }
+
+    #[test]
+    fn test_definition() {
+        let doc = Document::new(SRC);
+        assert_eq!(doc.definition(3, 8), Some((0, 7, 3)));
+        assert_eq!(doc.definition(2, 4), None);
+    }
+
+    #[test]
+    fn test_completion_contains_mnemonics_and_constants() {
+        let items = Document::new(SRC).completion();
+        assert!(items.iter().any(|(l, _, c)| l == "HALT" && !c));
+        assert!(
+            items
+                .iter()
+                .any(|(l, d, c)| l == "led" && d == ".const led 0xF" && *c)
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_survive_bad_lines() {
+        let doc = Document::new("LOADI #1 ; x\n$$$\nHALT");
+        assert_eq!(
+            doc.semantic_tokens(Encoding::Utf16),
+            vec![
+                0, 0, 5, 0, 0, 0, 6, 1, 4, 0, 0, 1, 1, 2, 0, 0, 2, 3, 5, 0, 2, 0, 4, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_columns_in_client_encoding() {
+        // `é` is 2 bytes but 1 utf-16 unit, `𝄞` 4 bytes but 2 units
+        let doc = Document::new("HALT ; é𝄞 x");
+        assert_eq!(doc.client_col(Encoding::Utf16, 0, 9), 8);
+        assert_eq!(doc.client_col(Encoding::Utf16, 0, 13), 10);
+        assert_eq!(doc.client_col(Encoding::Utf8, 0, 13), 13);
+        assert_eq!(doc.client_col(Encoding::Utf16, 0, 16), 13);
+        assert_eq!(doc.byte_col(Encoding::Utf16, 0, 10), 13);
+        assert_eq!(doc.byte_col(Encoding::Utf16, 0, 13), 16);
+        assert_eq!(
+            doc.semantic_tokens(Encoding::Utf16),
+            vec![0, 0, 4, 0, 0, 0, 5, 7, 5, 0]
+        );
+        assert_eq!(
+            doc.semantic_tokens(Encoding::Utf8),
+            vec![0, 0, 4, 0, 0, 0, 5, 10, 5, 0]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use serde_json::{Value, json};
+use shared::err::{Severity, T8Err};
+
+use crate::analysis::{Document, Encoding, TOKEN_MODIFIERS, TOKEN_TYPES};
+
+mod analysis;
+
+/// Reads the body of a single `Content-Length` framed message, `None` once stdin is closed.
+/// Broken framing is an error, without a length the next message can not be found.
+fn read_message<R: BufRead>(r: &mut R) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            len = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; len.ok_or("Missing Content-Length header")?];
+    r.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message<W: Write>(w: &mut W, msg: &Value) -> Result<(), Box<dyn std::error::Error>> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    Ok(w.flush()?)
+}
+
+/// range of `len` bytes at the byte column `col`, converted to the client's `encoding`
+fn range(doc: &Document, encoding: Encoding, line: usize, col: usize, len: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": doc.client_col(encoding, line, col) },
+        "end": { "line": line, "character": doc.client_col(encoding, line, col + len) },
+    })
+}
+
+fn diagnostic(doc: &Document, encoding: Encoding, uri: &str, err: &T8Err) -> Value {
+    let mut message = err.msg.clone();
+    for help in &err.help {
+        message.push_str(&format!("\nhelp: {help}"));
+    }
+    json!({
+        "range": range(doc, encoding, err.line, err.col, err.len),
+        "severity": match err.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        },
+        "code": err.code,
+        "source": "t8",
+        "message": message,
+        "relatedInformation": err.labels.iter().map(|l| json!({
+            "location": { "uri": uri, "range": range(doc, encoding, l.line, l.col, l.len) },
+            "message": l.msg,
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// (uri, line, character) of a `TextDocumentPositionParams`
+fn position(params: &Value) -> Option<(&str, usize, usize)> {
+    Some((
+        params["textDocument"]["uri"].as_str()?,
+        params["position"]["line"].as_u64()? as usize,
+        params["position"]["character"].as_u64()? as usize,
+    ))
+}
+
+/// a json-rpc error response, `id` is null if the message's id could not be read
+fn error(id: &Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+#[derive(Default)]
+struct Server {
+    docs: HashMap<String, String>,
+    /// negotiated in `initialize`
+    encoding: Encoding,
+}
+
+impl Server {
+    fn publish<W: Write>(&self, w: &mut W, uri: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let src = self.docs.get(uri);
+        let doc = Document::new(src.map(String::as_str).unwrap_or_default());
+        let diagnostics = src.map(|_| doc.diagnostics(uri)).unwrap_or_default();
+        write_message(
+            w,
+            &json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                    "uri": uri,
+                    "diagnostics": diagnostics
+                        .iter()
+                        .map(|d| diagnostic(&doc, self.encoding, uri, d))
+                        .collect::<Vec<_>>(),
+                },
+            }),
+        )
+    }
+
+    /// result of a request, `Err` holds a json-rpc error code and message
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let doc = |uri: &str| {
+            self.docs
+                .get(uri)
+                .map(|src| Document::new(src))
+                .ok_or((-32602, format!("Unknown document {uri}")))
+        };
+        let invalid = || (-32602, format!("Invalid params for {method}"));
+
+        let encoding = self.encoding;
+        Ok(match method {
+            "initialize" => {
+                // utf-16 unless the client also speaks utf-8, which saves converting columns
+                let offered = params["capabilities"]["general"]["positionEncodings"]
+                    .as_array()
+                    .is_some_and(|e| e.iter().any(|e| e == Encoding::Utf8.as_str()));
+                self.encoding = if offered {
+                    Encoding::Utf8
+                } else {
+                    Encoding::Utf16
+                };
+                json!({
+                    "capabilities": {
+                        "positionEncoding": self.encoding.as_str(),
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "completionProvider": {},
+                        "semanticTokensProvider": {
+                            "legend": {
+                                "tokenTypes": TOKEN_TYPES,
+                                "tokenModifiers": TOKEN_MODIFIERS,
+                            },
+                            "full": true,
+                        },
+                    },
+                    "serverInfo": { "name": "t8-lsp", "version": env!("CARGO_PKG_VERSION") },
+                })
+            }
+            "shutdown" => Value::Null,
+            "textDocument/hover" => {
+                let (uri, line, col) = position(params).ok_or_else(invalid)?;
+                let doc = doc(uri)?;
+                match doc.hover(line, doc.byte_col(encoding, line, col)) {
+                    Some(md) => json!({ "contents": { "kind": "markdown", "value": md } }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/definition" => {
+                let (uri, line, col) = position(params).ok_or_else(invalid)?;
+                let doc = doc(uri)?;
+                match doc.definition(line, doc.byte_col(encoding, line, col)) {
+                    Some((line, col, len)) => {
+                        json!({ "uri": uri, "range": range(&doc, encoding, line, col, len) })
+                    }
+                    None => Value::Null,
+                }
+            }
+            "textDocument/completion" => {
+                let (uri, ..) = position(params).ok_or_else(invalid)?;
+                let items = doc(uri)?
+                    .completion()
+                    .into_iter()
+                    .map(|(label, detail, constant)| {
+                        // 14 = Keyword, 21 = Constant
+                        json!({
+                            "label": label,
+                            "detail": detail,
+                            "kind": if constant { 21 } else { 14 },
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!(items)
+            }
+            "textDocument/semanticTokens/full" => {
+                let uri = params["textDocument"]["uri"].as_str().ok_or_else(invalid)?;
+                json!({ "data": doc(uri)?.semantic_tokens(encoding) })
+            }
+            _ => return Err((-32601, format!("Unknown method {method}"))),
+        })
+    }
+
+    /// Handles a single message body, returns true on `exit`. Bodies that are no json or no
+    /// json-rpc message are answered with an error response instead of ending the server.
+    fn handle<W: Write>(
+        &mut self,
+        w: &mut W,
+        body: &[u8],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let msg = match serde_json::from_slice::<Value>(body) {
+            Ok(msg) => msg,
+            Err(e) => {
+                write_message(w, &error(&Value::Null, -32700, format!("Parse error: {e}")))?;
+                return Ok(false);
+            }
+        };
+        let params = &msg["params"];
+        match (msg.get("id"), msg.get("method").map(Value::as_str)) {
+            (Some(id), Some(Some(method))) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => error(id, code, message),
+                };
+                write_message(w, &response)?;
+            }
+            (None, Some(Some(method))) => return self.notify(w, method, params),
+            // responses to requests we never send
+            (Some(_), None) if msg.get("result").is_some() || msg.get("error").is_some() => {}
+            (id, _) => {
+                let id = id.unwrap_or(&Value::Null);
+                write_message(w, &error(id, -32600, "Invalid request".into()))?;
+            }
+        }
+        Ok(false)
+    }
+
+    /// handles a notification, returns true on `exit`
+    fn notify<W: Write>(
+        &mut self,
+        w: &mut W,
+        method: &str,
+        params: &Value,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let uri = params["textDocument"]["uri"].as_str().map(String::from);
+        match (method, uri) {
+            ("exit", _) => return Ok(true),
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.docs.insert(uri.clone(), text.to_string());
+                self.publish(w, &uri)?;
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // full sync, the last change holds the whole document
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.docs.insert(uri.clone(), text.to_string());
+                }
+                self.publish(w, &uri)?;
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.docs.remove(&uri);
+                self.publish(w, &uri)?;
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut server = Server::default();
+
+    while let Some(body) = read_message(&mut stdin)? {
+        if server.handle(&mut stdout, &body)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialize_negotiates_position_encoding() {
+        let mut server = Server::default();
+        let offers_utf8 =
+            json!({ "capabilities": { "general": { "positionEncodings": ["utf-8", "utf-16"] } } });
+        let result = server.request("initialize", &offers_utf8).unwrap();
+        assert_eq!(result["capabilities"]["positionEncoding"], "utf-8");
+        assert_eq!(server.encoding, Encoding::Utf8);
+
+        let result = server.request("initialize", &json!({})).unwrap();
+        assert_eq!(result["capabilities"]["positionEncoding"], "utf-16");
+        assert_eq!(server.encoding, Encoding::Utf16);
+    }
+
+    /// bodies of the framed messages `server` wrote for `body`
+    fn handle(server: &mut Server, body: &[u8]) -> Vec<Value> {
+        let mut out = vec![];
+        assert!(!server.handle(&mut out, body).unwrap());
+        let mut r = out.as_slice();
+        std::iter::from_fn(|| read_message(&mut r).unwrap())
+            .map(|body| serde_json::from_slice(&body).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_malformed_messages_are_answered() {
+        let mut server = Server::default();
+        let parse = handle(&mut server, b"{\"id\": 1, ");
+        assert_eq!(parse[0]["id"], Value::Null);
+        assert_eq!(parse[0]["error"]["code"], -32700);
+
+        for (body, id) in [
+            (&b"[1, 2]"[..], Value::Null),
+            (b"{\"id\": 2, \"method\": 3}", json!(2)),
+            (b"{\"jsonrpc\": \"2.0\"}", Value::Null),
+        ] {
+            let invalid = handle(&mut server, body);
+            assert_eq!(invalid[0]["id"], id);
+            assert_eq!(invalid[0]["error"]["code"], -32600);
+        }
+
+        // still serving
+        let shutdown = handle(&mut server, b"{\"id\": 3, \"method\": \"shutdown\"}");
+        assert_eq!(shutdown[0]["result"], Value::Null);
+        // a response to a request the server never sent needs no answer
+        assert!(handle(&mut server, b"{\"id\": 1, \"result\": null}").is_empty());
+    }
+}
@@ -12,6 +12,43 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    /// every instruction of the ISA in opcode order, operands are zeroed
+    pub const ALL: [Instruction; 9] = [
+        Instruction::NOP,
+        Instruction::LOADI { imm: 0 },
+        Instruction::MOV,
+        Instruction::ADD,
+        Instruction::SUB,
+        Instruction::ST { addr: 0 },
+        Instruction::LD { addr: 0 },
+        Instruction::ROL { imm: 0 },
+        Instruction::HALT,
+    ];
+
+    /// description as listed in doc/isa.md
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::NOP => "No operation",
+            Self::LOADI { .. } => "Load immediate into AC",
+            Self::MOV => "AC -> DEST",
+            Self::ADD => "DEST += AC",
+            Self::SUB => "DEST -= AC",
+            Self::ST { .. } => "write AC into addr",
+            Self::LD { .. } => "load byte at addr into AC",
+            Self::ROL { .. } => "Rotate AC left by `imm` bits",
+            Self::HALT => "Stop CPU",
+        }
+    }
+
+    /// kind of the operand as listed in doc/isa.md, `None` for operandless instructions
+    pub fn operand(&self) -> Option<&'static str> {
+        match self {
+            Self::LOADI { .. } | Self::ROL { .. } => Some("imm"),
+            Self::ST { .. } | Self::LD { .. } => Some("addr"),
+            Self::NOP | Self::MOV | Self::ADD | Self::SUB | Self::HALT => None,
+        }
+    }
+
     pub fn encode(&self) -> Option<u8> {
         Some(match self {
             Instruction::NOP => 0x00,
@@ -119,7 +156,7 @@ impl TryFrom<Instruction> for u8 {
     fn try_from(value: Instruction) -> Result<Self, Self::Error> {
         value
             .encode()
-            .ok_or_else(|| "Failed to encode instruction, rhs too large")
+            .ok_or("Failed to encode instruction, rhs too large")
     }
 }
 
@@ -143,8 +180,8 @@ mod tests {
 
         for inst in instructions {
             let encoded: u8 = inst.encode().unwrap();
-            let decoded =
-                Instruction::decode(encoded).expect(&format!("Failed to decode {:?}", inst));
+            let decoded = Instruction::decode(encoded)
+                .unwrap_or_else(|_| panic!("Failed to decode {:?}", inst));
             assert_eq!(inst, decoded);
         }
     }
@@ -0,0 +1,367 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{asm::Instruction, memmap, scriptorium::InvalidByte};
+
+/// t8.toml, the memory map and devices shared by `as`, `dis` and `emu`
+#[derive(Default, Debug, Deserialize)]
+pub struct Config {
+    /// `emu` only: print a trace line per step
+    #[serde(default)]
+    pub verbose: bool,
+    #[serde(default)]
+    pub io: HashMap<String, Device>,
+    #[serde(default)]
+    pub memory: Memory,
+}
+
+/// the `[memory]` table
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Memory {
+    /// in bytes, at most 16 since `ST` and `LD` address with 4 bits
+    pub size: u8,
+    /// inclusive `[from, to]` address ranges, `ST` and `LD` on them fault
+    pub reserved: Vec<[u8; 2]>,
+    /// path of a raw image loaded at address 0 before the program starts
+    pub image: Option<String>,
+    /// values of single cells before the program starts, keyed by `0x` prefixed hex or decimal
+    /// address, applied after `image`
+    pub init: HashMap<String, u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            size: 16,
+            reserved: vec![],
+            image: None,
+            init: HashMap::new(),
+        }
+    }
+}
+
+impl Memory {
+    /// whether `ST` and `LD` may access `addr`
+    pub fn accessible(&self, addr: u8) -> bool {
+        addr < self.size
+            && !self
+                .reserved
+                .iter()
+                .any(|[from, to]| (*from..=*to).contains(&addr))
+    }
+
+    /// Contents of the memory before the program starts, see [`Memory::image`] and
+    /// [`Memory::init`].
+    pub fn initial(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let size = self.size as usize;
+        let mut mem = vec![0; size];
+        if let Some(path) = &self.image {
+            let image = std::fs::read(path)
+                .map_err(|e| format!("Failed to read memory image `{path}`: {e}"))?;
+            if image.len() > size {
+                return Err(format!(
+                    "Memory image `{path}` has {} bytes, memory only {size}",
+                    image.len()
+                )
+                .into());
+            }
+            mem[..image.len()].copy_from_slice(&image);
+        }
+        for (key, value) in &self.init {
+            let addr = memmap::init_addr(key)
+                .ok_or_else(|| format!("Invalid address `{key}` in memory init"))?;
+            if !self.accessible(addr) {
+                return Err(format!(
+                    "Memory init of 0x{addr:X} is reserved or outside of the {size} byte memory"
+                )
+                .into());
+            }
+            mem[addr as usize] = *value;
+        }
+        Ok(mem)
+    }
+}
+
+#[derive(Default, Debug, Deserialize)]
+pub struct Device {
+    pub addr: u8,
+    /// where the device writes to, `None` renders to the terminal, see [`Kind`]
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub kind: Kind,
+    /// `sevenseg` only: bytes are raw segment masks instead of hex digits
+    #[serde(default)]
+    pub segments: bool,
+    /// `console` only: bytes are written as two hex digits per line instead of as is
+    #[serde(default)]
+    pub hex: bool,
+    /// `timer` only: executed instructions per tick, defaults to 1
+    #[serde(default)]
+    pub prescaler: Option<usize>,
+    /// `rng` only: the initial state, must not be 0, defaults to 1
+    #[serde(default)]
+    pub seed: Option<u8>,
+    /// `socket` only: path of the unix domain socket to connect to
+    #[serde(default)]
+    pub socket: Option<String>,
+    /// `socket` only: `LD` waits for a byte from the peer, defaults to true
+    #[serde(default)]
+    pub blocking: Option<bool>,
+}
+
+/// what a device does with the bytes written to it
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// every byte is appended to `file` as is
+    #[default]
+    File,
+    /// a bar of 8 LEDs, one per bit, most significant bit first
+    Led8,
+    /// a seven-segment display, see [`Device::segments`]
+    Sevenseg,
+    /// text on the terminal, see [`Device::hex`], `LD` reads a byte from stdin
+    Console,
+    /// `LD` reads the low 8 bits of the executed instructions divided by
+    /// [`Device::prescaler`], writes are ignored
+    Timer,
+    /// `LD` reads the next value of an LFSR starting at [`Device::seed`], writes are ignored
+    Rng,
+    /// a peripheral implemented by another process, bytes are exchanged over
+    /// [`Device::socket`], see [`Device::blocking`]
+    Socket,
+}
+
+impl Config {
+    /// Checks the memory map: the memory size, reserved regions and device addresses have to be
+    /// in range and devices may neither share an address nor lie in a reserved region. Returns
+    /// warnings for devices `program` never accesses.
+    pub fn validate(
+        &self,
+        program: &[Result<Instruction, InvalidByte>],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let size = self.memory.size;
+        if !(1..=16).contains(&size) {
+            return Err(format!("Memory size has to be between 1 and 16 bytes, got {size}").into());
+        }
+        for [from, to] in &self.memory.reserved {
+            if from > to || *to >= size {
+                return Err(format!(
+                    "Reserved region 0x{from:X}-0x{to:X} is not within the {size} byte memory"
+                )
+                .into());
+            }
+        }
+
+        let mut devices = self.io.iter().collect::<Vec<_>>();
+        devices.sort_by_key(|(name, dev)| (dev.addr, name.as_str()));
+        for pair in devices.windows(2) {
+            let ((a, dev), (b, other)) = (pair[0], pair[1]);
+            if dev.addr == other.addr {
+                return Err(format!(
+                    "Devices `{a}` and `{b}` are both mapped at 0x{:X}",
+                    dev.addr
+                )
+                .into());
+            }
+        }
+
+        let mut warnings = vec![];
+        for (name, dev) in devices {
+            let addr = dev.addr;
+            if addr >= size {
+                return Err(format!(
+                    "Device `{name}` at 0x{addr:X} is outside of the {size} byte memory"
+                )
+                .into());
+            }
+            if let Some([from, to]) = self
+                .memory
+                .reserved
+                .iter()
+                .find(|[from, to]| (*from..=*to).contains(&addr))
+            {
+                return Err(format!(
+                    "Device `{name}` at 0x{addr:X} lies in the reserved region 0x{from:X}-0x{to:X}"
+                )
+                .into());
+            }
+            let used = program.iter().any(|ins| {
+                matches!(ins, Ok(Instruction::ST { addr: a } | Instruction::LD { addr: a }) if *a == addr)
+            });
+            if !used {
+                warnings.push(format!(
+                    "Device `{name}` at 0x{addr:X} is never accessed by the program"
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+impl Config {
+    /// the default config with only `dev` mapped, named `name`
+    pub fn with_device(name: &str, dev: Device) -> Self {
+        let mut config = Config::default();
+        config.io.insert(name.into(), dev);
+        config
+    }
+
+    /// addresses of all memory mapped devices and of all preset cells, loading them is fine
+    /// without a store
+    pub fn addrs(&self) -> Vec<u8> {
+        let image = self
+            .memory
+            .image
+            .as_ref()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map_or(0, |meta| meta.len().min(self.memory.size as u64) as u8);
+        let init = self
+            .memory
+            .init
+            .keys()
+            .filter_map(|key| memmap::init_addr(key));
+        self.io
+            .values()
+            .map(|d| d.addr)
+            .chain(0..image)
+            .chain(init)
+            .collect()
+    }
+
+    /// problems the emulator rejects the config for but the lints can work around, like init
+    /// keys that are not an address and are left out of [`Config::addrs`]
+    pub fn warnings(&self) -> Vec<String> {
+        let mut keys = self
+            .memory
+            .init
+            .keys()
+            .filter(|key| memmap::init_addr(key).is_none())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| format!("Invalid address `{key}` in memory init, it is ignored"))
+            .collect()
+    }
+
+    /// device names and addresses sorted by address, names the assembler can not lex as an
+    /// identifier are skipped, since the disassembler's output has to reassemble
+    pub fn devices(&self) -> Vec<(String, u8)> {
+        let mut devices = self
+            .io
+            .iter()
+            .filter(|(name, dev)| is_ident(name) && dev.addr <= 0xF)
+            .map(|(name, dev)| (name.clone(), dev.addr))
+            .collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        devices
+    }
+}
+
+/// the assembler's identifier rule, a letter followed by letters, digits or `_`
+fn is_ident(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Instruction::*;
+
+    fn config(src: &str) -> Config {
+        toml::from_str(src).unwrap()
+    }
+
+    #[test]
+    fn test_validate_address_map() {
+        let program = [LOADI { imm: 1 }, ST { addr: 0x7 }, HALT].map(Ok);
+        let valid = config(
+            "verbose = false\n[memory]\nsize = 8\nreserved = [[0, 3]]\n\
+             [io.led]\naddr = 7\nkind = 'led8'\n[io.rng]\naddr = 6\nkind = 'rng'",
+        );
+        assert_eq!(
+            valid.validate(&program).unwrap(),
+            vec!["Device `rng` at 0x6 is never accessed by the program"]
+        );
+        assert!(valid.memory.accessible(7));
+        assert!(!valid.memory.accessible(2));
+        assert!(!valid.memory.accessible(8));
+
+        for (src, err) in [
+            (
+                "[io.a]\naddr = 1\n[io.b]\naddr = 1",
+                "Devices `a` and `b` are both mapped at 0x1",
+            ),
+            (
+                "[memory]\nsize = 4\n[io.led]\naddr = 0xF",
+                "Device `led` at 0xF is outside of the 4 byte memory",
+            ),
+            (
+                "[memory]\nreserved = [[8, 0xB]]\n[io.led]\naddr = 9",
+                "Device `led` at 0x9 lies in the reserved region 0x8-0xB",
+            ),
+            (
+                "[memory]\nsize = 17\n[io]",
+                "Memory size has to be between 1 and 16 bytes, got 17",
+            ),
+        ] {
+            let err_msg = config(&format!("verbose = false\n{src}"))
+                .validate(&program)
+                .unwrap_err()
+                .to_string();
+            assert_eq!(err_msg, err);
+        }
+    }
+
+    #[test]
+    fn test_initial_memory() {
+        let valid = config("verbose = false\n[io]\n[memory]\nsize = 6\ninit = { 0x3 = 5, 4 = 7 }");
+        assert_eq!(valid.memory.initial().unwrap(), vec![0, 0, 0, 5, 7, 0]);
+
+        for init in ["{ 6 = 1 }", "{ 0xZ = 1 }"] {
+            let invalid = config(&format!(
+                "verbose = false\n[io]\n[memory]\nsize = 6\ninit = {init}"
+            ));
+            assert!(invalid.memory.initial().is_err());
+        }
+    }
+
+    #[test]
+    fn test_addrs_and_warnings() {
+        let config =
+            config("[io]\nled = { addr = 0xF }\n[memory]\nsize = 8\ninit = { 0x3 = 5, 0xZ = 1 }");
+        let mut addrs = config.addrs();
+        addrs.sort();
+        assert_eq!(addrs, [3, 0xF]);
+        assert_eq!(
+            config.warnings(),
+            ["Invalid address `0xZ` in memory init, it is ignored"]
+        );
+    }
+
+    #[test]
+    fn test_devices_skip_non_identifiers() {
+        let config = Config {
+            io: ["led_2", "2led", "led-2", "", "out"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let dev = Device {
+                        addr: i as u8,
+                        ..Device::default()
+                    };
+                    (name.to_string(), dev)
+                })
+                .collect(),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.devices(),
+            [("led_2".to_string(), 0), ("out".to_string(), 4)]
+        );
+    }
+}
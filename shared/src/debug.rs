@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+
+/// first line of every debug sidecar
+pub const MAGIC: &str = "t8dbg";
+
+/// extension of the debug sidecar written next to each .t8b
+pub const EXTENSION: &str = "t8d";
+
+/// a `.const` definition and where it was defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u8,
+    pub line: usize,
+    pub col: usize,
+}
+
+/// source location of the instruction at `pc`, `operand` holds the symbol name if the operand
+/// was spelled as a constant instead of a number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loc {
+    pub pc: usize,
+    pub line: usize,
+    pub col: usize,
+    pub operand: Option<String>,
+}
+
+/// Maps program counters back to source positions and lists all symbols, emitted by the
+/// assembler alongside binaries as `<name>.t8d`:
+///
+/// ```text
+/// t8dbg
+/// file examples/led.t8
+/// const led 15 8 0
+/// loc 0 13 4 on
+/// loc 6 22 4
+/// ```
+///
+/// lines and columns are zero based, just like the lexer produces them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub file: String,
+    pub symbols: Vec<Symbol>,
+    pub locs: Vec<Loc>,
+}
+
+impl DebugInfo {
+    pub fn new<S: Into<String>>(file: S) -> Self {
+        DebugInfo {
+            file: file.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn loc(&self, pc: usize) -> Option<&Loc> {
+        self.locs.iter().find(|l| l.pc == pc)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    pub fn write<W: std::io::Write>(&self, w: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(w, "{MAGIC}")?;
+        writeln!(w, "file {}", self.file)?;
+        for Symbol {
+            name,
+            value,
+            line,
+            col,
+        } in &self.symbols
+        {
+            writeln!(w, "const {name} {value} {line} {col}")?;
+        }
+        for Loc {
+            pc,
+            line,
+            col,
+            operand,
+        } in &self.locs
+        {
+            match operand {
+                Some(operand) => writeln!(w, "loc {pc} {line} {col} {operand}")?,
+                None => writeln!(w, "loc {pc} {line} {col}")?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parse(src: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut lines = src.lines().enumerate();
+        if lines.next().map(|(_, l)| l) != Some(MAGIC) {
+            return Err("Invalid debug info header".into());
+        }
+
+        let mut info = DebugInfo::default();
+        for (i, line) in lines {
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            let num = |idx: usize| -> Result<usize, Box<dyn std::error::Error>> {
+                Ok(parts
+                    .get(idx)
+                    .ok_or_else(|| format!("Missing field in debug info line {}", i + 1))?
+                    .parse()?)
+            };
+            match parts.first() {
+                Some(&"file") => info.file = line["file".len()..].trim().to_string(),
+                Some(&"const") => info.symbols.push(Symbol {
+                    name: parts
+                        .get(1)
+                        .ok_or_else(|| format!("Missing name in debug info line {}", i + 1))?
+                        .to_string(),
+                    value: u8::try_from(num(2)?)?,
+                    line: num(3)?,
+                    col: num(4)?,
+                }),
+                Some(&"loc") => info.locs.push(Loc {
+                    pc: num(1)?,
+                    line: num(2)?,
+                    col: num(3)?,
+                    operand: parts.get(4).map(|s| s.to_string()),
+                }),
+                None => {}
+                Some(other) => {
+                    return Err(
+                        format!("Unknown debug info entry `{other}` in line {}", i + 1).into(),
+                    );
+                }
+            }
+        }
+        Ok(info)
+    }
+
+    /// loads the sidecar belonging to the binary at `binary`, if there is one
+    pub fn load_for(binary: &Path) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = sidecar(binary);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self::parse(&std::fs::read_to_string(path)?)?))
+    }
+}
+
+/// path of the debug sidecar for the binary at `binary`
+pub fn sidecar(binary: &Path) -> PathBuf {
+    binary.with_extension(EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_parse_roundtrip() {
+        let info = DebugInfo {
+            file: "examples/led.t8".into(),
+            symbols: vec![Symbol {
+                name: "led".into(),
+                value: 0xF,
+                line: 8,
+                col: 0,
+            }],
+            locs: vec![
+                Loc {
+                    pc: 0,
+                    line: 13,
+                    col: 4,
+                    operand: Some("led".into()),
+                },
+                Loc {
+                    pc: 1,
+                    line: 14,
+                    col: 4,
+                    operand: None,
+                },
+            ],
+        };
+
+        let mut buf = Vec::new();
+        info.write(&mut buf).unwrap();
+        let parsed = DebugInfo::parse(str::from_utf8(&buf).unwrap()).unwrap();
+        assert_eq!(info, parsed);
+    }
+
+    #[test]
+    fn test_parse_invalid_header() {
+        assert!(DebugInfo::parse("t8cpu\nfile x").is_err());
+    }
+}
@@ -1,34 +1,403 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, io::Write};
 
-#[derive(Debug)]
+use serde_json::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+/// secondary span pointing at related source, for instance where a constant was defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+    pub msg: String,
+}
+
+/// Diagnostic produced by the lexer, parser and lowering. `line` and `col` are zero based and
+/// `len` is the amount of characters the primary span underlines.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct T8Err {
     pub line: usize,
     pub col: usize,
+    pub len: usize,
+    /// false for diagnostics not about a place in the source, like a missing input file, they
+    /// render without the source frame, see [`T8Err::without_location`]
+    pub located: bool,
+    pub severity: Severity,
+    /// stable identifier of the kind of diagnostic, for instance `E0302`
+    pub code: Option<&'static str>,
     pub msg: String,
+    pub labels: Vec<Label>,
+    pub help: Vec<String>,
+}
+
+/// how diagnostics are written, selected via `--message-format=<human|json>`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    /// one json object per line on stdout, for editor integration
+    Json,
+}
+
+impl MessageFormat {
+    /// parses `--message-format=<format>`, returns `None` if `arg` is a different argument
+    pub fn from_arg(arg: &str) -> Option<Result<Self, String>> {
+        let format = arg.strip_prefix("--message-format=")?;
+        Some(match format {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown message format `{format}`, wanted `human` or `json`"
+            )),
+        })
+    }
+
+    /// writes `err` in this format, human output goes to stderr, json to stdout
+    pub fn emit<S: std::fmt::Display>(
+        &self,
+        err: &T8Err,
+        file: &str,
+        lines: &[S],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Human => err.render(&mut std::io::stderr(), file, lines, color_enabled()),
+            Self::Json => {
+                let mut stdout = std::io::stdout().lock();
+                writeln!(stdout, "{}", err.to_json(file))?;
+                Ok(stdout.flush()?)
+            }
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+
+/// whether diagnostics written to stderr should be colored, respects `NO_COLOR`
+pub fn color_enabled() -> bool {
+    use std::io::IsTerminal;
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
 }
 
 impl T8Err {
+    pub fn new<S: Into<String>>(line: usize, col: usize, msg: S) -> Self {
+        T8Err {
+            line,
+            col,
+            len: 1,
+            located: true,
+            severity: Severity::Error,
+            code: None,
+            msg: msg.into(),
+            labels: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn warning<S: Into<String>>(line: usize, col: usize, msg: S) -> Self {
+        T8Err {
+            severity: Severity::Warning,
+            ..Self::new(line, col, msg)
+        }
+    }
+
+    /// an error about the invocation rather than the source, for instance a missing input file
+    pub fn without_location<S: Into<String>>(msg: S) -> Self {
+        T8Err {
+            located: false,
+            ..Self::new(0, 0, msg)
+        }
+    }
+
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = len.max(1);
+        self
+    }
+
+    pub fn with_label<S: Into<String>>(
+        mut self,
+        line: usize,
+        col: usize,
+        len: usize,
+        msg: S,
+    ) -> Self {
+        self.labels.push(Label {
+            line,
+            col,
+            len: len.max(1),
+            msg: msg.into(),
+        });
+        self
+    }
+
+    pub fn with_help<S: Into<String>>(mut self, msg: S) -> Self {
+        self.help.push(msg.into());
+        self
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Serializes the diagnostic as a single line of json, lines and columns are one based like
+    /// in the rendered output and `len` is the amount of characters spanned, all three are `null`
+    /// without a location, like `file` if it is empty:
+    ///
+    /// ```text
+    /// {"code":"E0302","col":7,"file":"a.t8","help":[".."],"labels":[{"col":1,"len":10,"line":1,"message":"constant defined here"}],"len":4,"line":3,"message":"..","severity":"error"}
+    /// ```
+    pub fn to_json(&self, file: &str) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .map(|l| {
+                json!({
+                    "line": l.line + 1,
+                    "col": l.col + 1,
+                    "len": l.len,
+                    "message": l.msg,
+                })
+            })
+            .collect::<Vec<_>>();
+        let at = |n: usize| self.located.then_some(n);
+        json!({
+            "file": (self.located || !file.is_empty()).then_some(file),
+            "line": at(self.line + 1),
+            "col": at(self.col + 1),
+            "len": at(self.len),
+            "severity": self.severity.as_str(),
+            "code": self.code,
+            "message": self.msg,
+            "labels": labels,
+            "help": self.help,
+        })
+        .to_string()
+    }
+
+    /// Renders the diagnostic in the style of rustc:
+    ///
+    /// ```text
+    /// error: Operand `big` does not fit into 4 bits
+    ///  --> examples/led.t8:3:7
+    ///   |
+    /// 1 | .const big 20
+    ///   | ------------- constant defined here
+    /// 2 |
+    /// 3 | LOADI #big
+    ///   |       ^^^^
+    ///   |
+    ///   = help: build larger values via LOADI, ROL and ADD
+    /// ```
     pub fn render<'r, W, S>(
         &self,
         w: &'r mut W,
+        file: &str,
         lines: &'r [S],
+        color: bool,
     ) -> Result<(), Box<dyn std::error::Error>>
     where
         W: std::io::Write,
         S: std::fmt::Display,
     {
-        let start = self.line.saturating_sub(2);
-        let end = (self.line + 3).min(lines.len());
-
-        for i in start..end {
-            writeln!(w, "{:02} | {}", i + 1, &lines[i])?;
-            if i == self.line {
-                let pad = " ".repeat(self.col.saturating_sub(1));
-                writeln!(w, "   |{pad}^ {}", self.msg)?;
+        let paint = |code: &'static str| if color { code } else { "" };
+        let reset = paint(RESET);
+        let gutter_color = paint(BLUE);
+
+        // every line with a span attached, the primary span is marked by `None`
+        let mut spans = self
+            .labels
+            .iter()
+            .map(|l| (l.line, l.col, l.len, Some(l.msg.as_str())))
+            .chain(std::iter::once((self.line, self.col, self.len, None)))
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|(line, col, ..)| (*line, *col));
+
+        let last_line = spans.iter().map(|(line, ..)| *line).max().unwrap_or(0);
+        let width = (last_line + 1).to_string().len();
+        let gutter = " ".repeat(width);
+
+        let code = self.code.map(|c| format!("[{c}]")).unwrap_or_default();
+        writeln!(
+            w,
+            "{}{}{code}{reset}{}: {}{reset}",
+            paint(self.severity.color()),
+            self.severity.as_str(),
+            paint(BOLD),
+            self.msg
+        )?;
+        if !self.located {
+            for help in &self.help {
+                writeln!(
+                    w,
+                    " {gutter_color}={reset} {}help{reset}: {help}",
+                    paint(CYAN)
+                )?;
+            }
+            writeln!(w)?;
+            w.flush()?;
+            return Ok(());
+        }
+        writeln!(
+            w,
+            "{gutter}{gutter_color}-->{reset} {file}:{}:{}",
+            self.line + 1,
+            self.col + 1
+        )?;
+        writeln!(w, "{gutter} {gutter_color}|{reset}")?;
+
+        let source_line = |line: usize| {
+            let src = lines.get(line).map(|l| l.to_string()).unwrap_or_default();
+            let gutter = format!("{gutter_color}{:>width$} |{reset}", line + 1);
+            if src.is_empty() {
+                gutter
+            } else {
+                format!("{gutter} {src}")
+            }
+        };
+
+        let mut previous = None;
+        for (line, col, len, msg) in &spans {
+            if previous != Some(*line) {
+                // show the line between two spans instead of eliding a single line
+                if let Some(previous) = previous
+                    && line - previous == 2
+                {
+                    writeln!(w, "{}", source_line(previous + 1))?;
+                } else if previous.is_some_and(|p| line - p > 2) {
+                    writeln!(w, "{gutter_color}...{reset}")?;
+                }
+                writeln!(w, "{}", source_line(*line))?;
+                previous = Some(*line);
+            }
+
+            let pad = " ".repeat(*col);
+            match msg {
+                None => writeln!(
+                    w,
+                    "{gutter} {gutter_color}|{reset} {pad}{}{}{reset}",
+                    paint(self.severity.color()),
+                    "^".repeat(*len)
+                )?,
+                Some(msg) => writeln!(
+                    w,
+                    "{gutter} {gutter_color}|{reset} {pad}{gutter_color}{} {msg}{reset}",
+                    "-".repeat(*len)
+                )?,
             }
         }
 
+        if !self.help.is_empty() {
+            writeln!(w, "{gutter} {gutter_color}|{reset}")?;
+        }
+        for help in &self.help {
+            writeln!(
+                w,
+                "{gutter} {gutter_color}={reset} {}help{reset}: {help}",
+                paint(CYAN)
+            )?;
+        }
+        writeln!(w)?;
+
         w.flush()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_spans_labels_and_help() {
+        let lines = [".const big 20", "", "LOADI #big"];
+        let err = T8Err::new(2, 6, "Operand `big` does not fit into 4 bits")
+            .with_len(4)
+            .with_label(0, 0, 13, "constant defined here")
+            .with_help("build larger values via LOADI, ROL and ADD");
+
+        let mut buf = Vec::new();
+        err.render(&mut buf, "big.t8", &lines, false).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "\
+error: Operand `big` does not fit into 4 bits
+ --> big.t8:3:7
+  |
+1 | .const big 20
+  | ------------- constant defined here
+2 |
+3 | LOADI #big
+  |       ^^^^
+  |
+  = help: build larger values via LOADI, ROL and ADD
+
+"
+        );
+    }
+
+    #[test]
+    fn test_render_without_location() {
+        let err = T8Err::without_location("Missing .lisp file")
+            .with_code("E0001")
+            .with_help("pass the file to compile");
+        let mut buf = Vec::new();
+        err.render(&mut buf, "", &[] as &[&str], false).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "error[E0001]: Missing .lisp file\n = help: pass the file to compile\n\n"
+        );
+        assert_eq!(
+            err.to_json(""),
+            r#"{"code":"E0001","col":null,"file":null,"help":["pass the file to compile"],"labels":[],"len":null,"line":null,"message":"Missing .lisp file","severity":"error"}"#
+        );
+    }
+
+    #[test]
+    fn test_render_color() {
+        let mut buf = Vec::new();
+        T8Err::warning(0, 0, "unused")
+            .render(&mut buf, "x.t8", &["NOP"], true)
+            .unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.starts_with("\x1b[1;33mwarning\x1b[0m"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let err = T8Err::new(2, 6, "Undefined identifier `\"x\"`")
+            .with_len(2)
+            .with_code("E0301")
+            .with_label(0, 0, 3, "here")
+            .with_help("define it");
+        assert_eq!(
+            err.to_json("dir\\a.t8"),
+            r#"{"code":"E0301","col":7,"file":"dir\\a.t8","help":["define it"],"labels":[{"col":1,"len":3,"line":1,"message":"here"}],"len":2,"line":3,"message":"Undefined identifier `\"x\"`","severity":"error"}"#
+        );
+    }
+}
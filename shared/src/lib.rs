@@ -1,7 +1,16 @@
 /// t8cpu ISA de- and encoding
 pub mod asm;
+/// t8.toml, read by every tool knowing about the memory map
+pub mod config;
+/// source mapping and symbols emitted alongside binaries
+pub mod debug;
 /// rendering utility for displaying pretty errors
 pub mod err;
+/// memory map conventions of t8.toml shared by the tools reading it
+pub mod memmap;
+/// peephole optimization of instruction streams
+pub mod opt;
 /// t8cpu machine code packing
 pub mod scriptorium;
-
+/// shortest instruction sequences producing a constant
+pub mod superopt;
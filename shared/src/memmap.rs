@@ -0,0 +1,22 @@
+/// Address of a `[memory] init` key in t8.toml, `0x` prefixed hex or decimal, `None` if `key` is
+/// neither.
+pub fn init_addr(key: &str) -> Option<u8> {
+    match key.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => key.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_addr() {
+        assert_eq!(init_addr("0xA"), Some(10));
+        assert_eq!(init_addr("12"), Some(12));
+        for key in ["0xZ", "", "0x", "A", "256"] {
+            assert_eq!(init_addr(key), None, "{key}");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::asm::Instruction;
+
+/// what is known about the registers before an instruction
+#[derive(Debug, Default, Clone, Copy)]
+struct State {
+    ac: Option<u8>,
+    dest: Option<u8>,
+    /// DEST holds the same value as AC, even if that value is unknown
+    dest_is_ac: bool,
+}
+
+impl State {
+    fn set_ac(&mut self, ac: Option<u8>) {
+        self.ac = ac;
+        self.dest_is_ac = ac.is_some() && ac == self.dest;
+    }
+}
+
+/// Forward pass, drops instructions not changing any register given what is known about AC and
+/// DEST at that point: NOPs, LOADIs of the value AC already holds, MOVs while DEST already holds AC
+/// and rotations by a multiple of 8.
+fn redundant(ins: &[Instruction], keep: &mut [bool]) {
+    let mut state = State::default();
+    for (i, ins) in ins.iter().enumerate() {
+        if !keep[i] {
+            continue;
+        }
+        match *ins {
+            Instruction::NOP => keep[i] = false,
+            Instruction::LOADI { imm } if state.ac == Some(imm) => keep[i] = false,
+            Instruction::LOADI { imm } => state.set_ac(Some(imm)),
+            Instruction::MOV if state.dest_is_ac => keep[i] = false,
+            Instruction::MOV => {
+                state.dest = state.ac;
+                state.dest_is_ac = true;
+            }
+            Instruction::ADD => {
+                state.set_ac(state.dest.zip(state.ac).map(|(d, a)| d.wrapping_add(a)))
+            }
+            Instruction::SUB => {
+                state.set_ac(state.dest.zip(state.ac).map(|(d, a)| d.wrapping_sub(a)))
+            }
+            Instruction::ROL { imm } if imm & 0x7 == 0 => keep[i] = false,
+            Instruction::ROL { imm } => {
+                state.set_ac(state.ac.map(|a| a.rotate_left((imm & 0xF) as u32)))
+            }
+            Instruction::LD { .. } => state.set_ac(None),
+            Instruction::ST { .. } => {}
+            // unreachable from here on, nothing is known about what follows
+            Instruction::HALT => state = State::default(),
+        }
+    }
+}
+
+/// Backward pass, drops instructions whose result is overwritten before it is read. LD is kept
+/// even if dead, reading a device may have side effects.
+fn dead(ins: &[Instruction], keep: &mut [bool]) {
+    // the registers are observable once the program halts or ends
+    let (mut ac_live, mut dest_live) = (true, true);
+    for (i, ins) in ins.iter().enumerate().rev() {
+        if !keep[i] {
+            continue;
+        }
+        match ins {
+            Instruction::LOADI { .. }
+            | Instruction::ROL { .. }
+            | Instruction::ADD
+            | Instruction::SUB
+                if !ac_live =>
+            {
+                keep[i] = false
+            }
+            Instruction::MOV if !dest_live => keep[i] = false,
+            Instruction::LOADI { .. } | Instruction::LD { .. } => ac_live = false,
+            Instruction::ROL { .. } | Instruction::ST { .. } => ac_live = true,
+            Instruction::MOV => (ac_live, dest_live) = (true, false),
+            Instruction::ADD | Instruction::SUB => (ac_live, dest_live) = (true, true),
+            Instruction::HALT => (ac_live, dest_live) = (true, true),
+            Instruction::NOP => {}
+        }
+    }
+}
+
+/// Indexes of the instructions of `ins` surviving peephole optimization, in order. The
+/// optimized program stores the same values to the same addresses in the same order and halts
+/// with the same AC and DEST.
+pub fn optimize_indexes(ins: &[Instruction]) -> Vec<usize> {
+    let mut keep = vec![true; ins.len()];
+    loop {
+        let before = keep.iter().filter(|k| **k).count();
+        redundant(ins, &mut keep);
+        dead(ins, &mut keep);
+        if keep.iter().filter(|k| **k).count() == before {
+            break;
+        }
+    }
+    (0..ins.len()).filter(|i| keep[*i]).collect()
+}
+
+/// Removes redundant loads, moves, rotations and NOPs from `ins`, see [`optimize_indexes`]
+pub fn optimize(ins: &[Instruction]) -> Vec<Instruction> {
+    optimize_indexes(ins)
+        .into_iter()
+        .map(|i| ins[i].clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Instruction::*;
+
+    /// stores and final registers, enough to tell two programs apart
+    fn run(ins: &[Instruction]) -> (Vec<(u8, u8)>, u8, u8) {
+        let (mut ac, mut dest, mut mem, mut stores) = (0u8, 0u8, [0u8; 16], vec![]);
+        for ins in ins {
+            match *ins {
+                NOP => {}
+                HALT => break,
+                LOADI { imm } => ac = imm,
+                MOV => dest = ac,
+                ADD => ac = dest.wrapping_add(ac),
+                SUB => ac = dest.wrapping_sub(ac),
+                ST { addr } => {
+                    mem[addr as usize] = ac;
+                    stores.push((addr, ac));
+                }
+                LD { addr } => ac = mem[addr as usize],
+                ROL { imm } => ac = ac.rotate_left((imm & 0xF) as u32),
+            }
+        }
+        (stores, ac, dest)
+    }
+
+    #[test]
+    fn test_reuses_dest() {
+        // the H and E of examples/hello.t8
+        let ins = [
+            LOADI { imm: 4 },
+            ROL { imm: 4 },
+            MOV,
+            LOADI { imm: 8 },
+            ADD,
+            ST { addr: 0 },
+            LOADI { imm: 4 },
+            ROL { imm: 4 },
+            MOV,
+            LOADI { imm: 5 },
+            NOP,
+            ADD,
+            ST { addr: 0 },
+            HALT,
+        ];
+        let optimized = optimize(&ins);
+        assert_eq!(
+            optimized,
+            vec![
+                LOADI { imm: 4 },
+                ROL { imm: 4 },
+                MOV,
+                LOADI { imm: 8 },
+                ADD,
+                ST { addr: 0 },
+                LOADI { imm: 5 },
+                ADD,
+                ST { addr: 0 },
+                HALT,
+            ]
+        );
+        assert_eq!(run(&ins), run(&optimized));
+    }
+
+    #[test]
+    fn test_keeps_loads() {
+        let ins = [LD { addr: 3 }, LOADI { imm: 1 }, ST { addr: 3 }];
+        assert_eq!(optimize(&ins), ins);
+    }
+
+    #[test]
+    fn test_random_programs_are_equivalent() {
+        let mut seed: u32 = 0x7a3c_91e5;
+        for _ in 0..1024 {
+            let ins = (0..24)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    let ins = Instruction::ALL[seed as usize % 9].clone();
+                    match ins.operand() {
+                        Some(_) => {
+                            Instruction::decode(ins.op() | (seed >> 8) as u8 & 0xF).unwrap_or(NOP)
+                        }
+                        None => ins,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let optimized = optimize(&ins);
+            assert!(optimized.len() <= ins.len());
+            assert_eq!(run(&ins), run(&optimized), "{ins:?} -> {optimized:?}");
+        }
+    }
+}
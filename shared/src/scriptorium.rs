@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::asm::Instruction;
 
 pub struct Script<'script, W: std::io::Write> {
@@ -6,6 +8,34 @@ pub struct Script<'script, W: std::io::Write> {
 
 pub const MAGIC: &[u8] = b"t8cpu";
 
+/// unit of a t8 binary as produced by the assembler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Word {
+    Instruction(Instruction),
+    /// emitted verbatim, for instance via `.byte`
+    Byte(u8),
+}
+
+/// byte in a binary that does not decode to an instruction, `offset` is relative to the end of
+/// the header and thus equal to the pc it would be executed at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidByte {
+    pub offset: usize,
+    pub byte: u8,
+}
+
+impl fmt::Display for InvalidByte {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid instruction 0x{:02X} at offset {:04x}",
+            self.byte, self.offset
+        )
+    }
+}
+
+impl std::error::Error for InvalidByte {}
+
 impl<'script, W: std::io::Write> Script<'script, W> {
     pub fn new(w: &'script mut W) -> Result<Self, Box<dyn std::error::Error>> {
         w.write_all(MAGIC)?;
@@ -20,22 +50,157 @@ impl<'script, W: std::io::Write> Script<'script, W> {
         for i in instructions {
             self.w.write_all(&[i
                 .encode()
-                .ok_or_else(|| "Failed to encode instruction, rhs too large")?])?;
+                .ok_or("Failed to encode instruction, rhs too large")?])?;
         }
         Ok(())
     }
+
+    /// used in the assembler, since `.byte` can place arbitrary bytes between instructions
+    pub fn add_words(&mut self, words: &[Word]) -> Result<(), Box<dyn std::error::Error>> {
+        for w in words {
+            match w {
+                Word::Instruction(i) => self.add_instructions(std::slice::from_ref(i))?,
+                Word::Byte(b) => self.w.write_all(&[*b])?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// metadata stored in front of every t8 binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub magic: [u8; 5],
+}
+
+/// single decoded byte of a binary, `offset` is relative to the end of the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub offset: usize,
+    pub byte: u8,
+    pub decoded: Result<Instruction, InvalidByte>,
+}
+
+/// Streaming counterpart to [`Script`], validates the header on construction and yields every
+/// following byte as an [`Entry`]
+pub struct ScriptReader<R: std::io::Read> {
+    r: R,
+    header: Header,
+    offset: usize,
+}
+
+impl<R: std::io::Read> ScriptReader<R> {
+    pub fn new(mut r: R) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut magic = [0; 5];
+        r.read_exact(&mut magic).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => "Not enough bytes for a valid t8 binary".into(),
+            _ => Box::<dyn std::error::Error>::from(e),
+        })?;
+        if magic != MAGIC {
+            return Err("Invalid header".into());
+        }
+        Ok(ScriptReader {
+            r,
+            header: Header { magic },
+            offset: 0,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+}
+
+impl<R: std::io::Read> Iterator for ScriptReader<R> {
+    type Item = std::io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut byte = [0];
+        loop {
+            match self.r.read(&mut byte) {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let [byte] = byte;
+        let offset = self.offset;
+        self.offset += 1;
+        Some(Ok(Entry {
+            offset,
+            byte,
+            decoded: Instruction::decode(byte).map_err(|_| InvalidByte { offset, byte }),
+        }))
+    }
+}
+
+/// decodes every byte after the header, bytes not decoding to an instruction are kept in place as
+/// [`InvalidByte`], so executing or rendering them is up to the caller
+pub fn from(
+    bytes: &[u8],
+) -> Result<Vec<Result<Instruction, InvalidByte>>, Box<dyn std::error::Error>> {
+    Ok(ScriptReader::new(bytes)?
+        .map(|e| e.map(|e| e.decoded))
+        .collect::<Result<_, _>>()?)
 }
 
-pub fn from(bytes: &[u8]) -> Result<Vec<Instruction>, Box<dyn std::error::Error>> {
-    if bytes.len() < 5 {
-        return Err("Not enough bytes for a valid t8 binary".into());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_keeps_invalid_bytes() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([0x11, 0x9A, 0x80, 0xFF]);
+        assert_eq!(
+            from(&bytes).unwrap(),
+            vec![
+                Ok(Instruction::LOADI { imm: 1 }),
+                Err(InvalidByte {
+                    offset: 1,
+                    byte: 0x9A
+                }),
+                Ok(Instruction::HALT),
+                Err(InvalidByte {
+                    offset: 3,
+                    byte: 0xFF
+                }),
+            ]
+        );
     }
-    if &bytes[..5] != MAGIC {
-        return Err("Invalid header".into());
+
+    #[test]
+    fn test_reader_offsets_and_header() {
+        let mut bytes = Vec::new();
+        Script::new(&mut bytes)
+            .unwrap()
+            .add_instructions(&[Instruction::LOADI { imm: 3 }, Instruction::HALT])
+            .unwrap();
+
+        let mut reader = ScriptReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.header().magic, MAGIC);
+        let entries = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    offset: 0,
+                    byte: 0x13,
+                    decoded: Ok(Instruction::LOADI { imm: 3 }),
+                },
+                Entry {
+                    offset: 1,
+                    byte: 0x80,
+                    decoded: Ok(Instruction::HALT),
+                },
+            ]
+        );
     }
 
-    Ok(bytes[5..]
-        .iter()
-        .map(|b| Instruction::decode(*b).expect("Failed to decode instruction"))
-        .collect())
+    #[test]
+    fn test_reader_rejects_header() {
+        assert!(ScriptReader::new(&b"t8c"[..]).is_err());
+        assert!(ScriptReader::new(&b"t9cpu\x80"[..]).is_err());
+    }
 }
@@ -0,0 +1,166 @@
+use std::{collections::VecDeque, sync::OnceLock};
+
+use crate::asm::Instruction;
+
+/// what a sequence may assume about DEST and whether it has to keep it intact
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Constraints {
+    /// value DEST holds before the sequence, if known
+    pub dest: Option<u8>,
+    /// DEST holds its previous value after the sequence, rules out MOV
+    pub preserve_dest: bool,
+}
+
+/// AC and DEST during the search, `None` while a register's value is unknown
+type State = (Option<u8>, Option<u8>);
+
+const STATES: usize = 257 * 257;
+
+fn index((ac, dest): State) -> usize {
+    ac.map_or(256, usize::from) * 257 + dest.map_or(256, usize::from)
+}
+
+/// every instruction worth trying, rotations by 0 and 8 do nothing and 9..=15 repeat 1..=7
+fn candidates(preserve_dest: bool) -> Vec<Instruction> {
+    let mut candidates = (0..=0xF)
+        .map(|imm| Instruction::LOADI { imm })
+        .chain((1..=7).map(|imm| Instruction::ROL { imm }))
+        .chain([Instruction::ADD, Instruction::SUB])
+        .collect::<Vec<_>>();
+    if !preserve_dest {
+        candidates.push(Instruction::MOV);
+    }
+    candidates
+}
+
+/// state after executing `ins` in `(ac, dest)`, `None` if the result depends on an unknown
+fn step(ins: &Instruction, (ac, dest): State) -> Option<State> {
+    Some(match *ins {
+        Instruction::LOADI { imm } => (Some(imm), dest),
+        Instruction::ROL { imm } => (Some(ac?.rotate_left(imm as u32)), dest),
+        Instruction::ADD => (Some(dest?.wrapping_add(ac?)), dest),
+        Instruction::SUB => (Some(dest?.wrapping_sub(ac?)), dest),
+        Instruction::MOV => (Some(ac?), Some(ac?)),
+        _ => return None,
+    })
+}
+
+/// Breadth first search over all register states reachable from the state described by
+/// `constraints`, the first state reached holding a value in AC ends the shortest sequence
+/// producing it.
+pub fn table(constraints: Constraints) -> Vec<Option<Vec<Instruction>>> {
+    let candidates = candidates(constraints.preserve_dest);
+    let start = (None, constraints.dest);
+    // predecessor state and the instruction leading from it, per state
+    let mut prev: Vec<Option<(State, usize)>> = vec![None; STATES];
+    let mut seen = vec![false; STATES];
+    let mut found: Vec<Option<State>> = vec![None; 256];
+    let mut queue = VecDeque::from([start]);
+    seen[index(start)] = true;
+
+    while let Some(state) = queue.pop_front() {
+        for (i, ins) in candidates.iter().enumerate() {
+            let Some(next) = step(ins, state) else {
+                continue;
+            };
+            if seen[index(next)] {
+                continue;
+            }
+            seen[index(next)] = true;
+            prev[index(next)] = Some((state, i));
+            if let Some(ac) = next.0
+                && found[ac as usize].is_none()
+            {
+                found[ac as usize] = Some(next);
+            }
+            queue.push_back(next);
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|state| {
+            let mut state = state?;
+            let mut seq = vec![];
+            while let Some((from, i)) = prev[index(state)] {
+                seq.push(candidates[i].clone());
+                state = from;
+            }
+            seq.reverse();
+            Some(seq)
+        })
+        .collect()
+}
+
+/// tables for unknown DEST, searched once on first use, indexed by `preserve_dest`
+static TABLES: OnceLock<[Vec<Option<Vec<Instruction>>>; 2]> = OnceLock::new();
+/// tables for every known DEST, searched once on first use, indexed by DEST and `preserve_dest`
+static KNOWN: [OnceLock<Vec<Option<Vec<Instruction>>>>; 512] = [const { OnceLock::new() }; 512];
+
+/// Shortest sequence of LOADI, ROL, MOV, ADD and SUB leaving `value` in AC under
+/// `constraints`, `None` if there is none, for instance for 0x55 if DEST is unknown and has to
+/// be preserved.
+pub fn materialize(value: u8, constraints: Constraints) -> Option<Vec<Instruction>> {
+    if let Some(dest) = constraints.dest {
+        let known = &KNOWN[dest as usize * 2 + constraints.preserve_dest as usize];
+        return known.get_or_init(|| table(constraints))[value as usize].clone();
+    }
+    let tables = TABLES.get_or_init(|| {
+        [false, true].map(|preserve_dest| {
+            table(Constraints {
+                dest: None,
+                preserve_dest,
+            })
+        })
+    });
+    tables[constraints.preserve_dest as usize][value as usize].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::Instruction::*;
+
+    fn run(seq: &[Instruction], dest: u8) -> (u8, u8) {
+        let mut state = (None, Some(dest));
+        for ins in seq {
+            state = step(ins, state).expect("sequence reads an unknown register");
+        }
+        (state.0.unwrap(), state.1.unwrap())
+    }
+
+    #[test]
+    fn test_every_value_is_reachable() {
+        let table = table(Constraints::default());
+        for (value, seq) in table.iter().enumerate() {
+            let seq = seq
+                .as_ref()
+                .unwrap_or_else(|| panic!("0x{value:02X} unreachable"));
+            assert!(seq.len() <= 5, "0x{value:02X}: {seq:?}");
+            assert_eq!(run(seq, 0xAA).0, value as u8, "{seq:?}");
+        }
+        assert_eq!(table[0x0F], Some(vec![LOADI { imm: 0xF }]));
+        assert_eq!(table[0x48], Some(vec![LOADI { imm: 9 }, ROL { imm: 3 }]));
+    }
+
+    #[test]
+    fn test_preserve_dest() {
+        let preserve = Constraints {
+            dest: None,
+            preserve_dest: true,
+        };
+        assert_eq!(materialize(0x55, preserve), None);
+        assert_eq!(
+            materialize(0x80, preserve),
+            Some(vec![LOADI { imm: 1 }, ROL { imm: 7 }])
+        );
+
+        let known = Constraints {
+            dest: Some(0x50),
+            preserve_dest: true,
+        };
+        let seq = materialize(0x55, known).unwrap();
+        assert_eq!(seq, vec![LOADI { imm: 5 }, ADD]);
+        assert_eq!(run(&seq, 0x50), (0x55, 0x50));
+    }
+}